@@ -4,18 +4,41 @@
 //! optional traits.
 
 use ::std::time::Duration;
-use inputflow::{
-    cglue::{result::from_int_result, *},
-    error::InputFlowError,
-    prelude::*,
-};
-use std::ffi::CString;
+use inputflow::{cglue::*, prelude::*};
 use std::io;
-use std::mem::MaybeUninit;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 fn main() -> Result<()> {
+    // plugins log through the host logger, so it has to be set up before loading them
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    // safety: the plugin directories are assumed to only contain trusted libraries
+    let inventory = unsafe { Inventory::scan() };
+    println!("Available connectors:");
+    for plugin in inventory.available_plugins() {
+        println!(
+            "  {} v{} {:?} ({})",
+            plugin.name,
+            plugin.version,
+            plugin.features,
+            plugin.path.display()
+        );
+        if plugin.lists_devices {
+            match inventory.list_devices(&plugin.name) {
+                Ok(devices) => {
+                    for device in devices {
+                        println!("    {} with args {}", device.description, device.args);
+                    }
+                }
+                Err(e) => println!("    listing devices failed: {e}: {:?}", take_last_error()),
+            }
+        }
+    }
+
     let mut lib = String::new();
 
     println!("Enter name of the plugin library [inputflow_native]:");
@@ -30,78 +53,73 @@ fn main() -> Result<()> {
     let mut args = String::new();
     io::stdin().read_line(&mut args)?;
 
-    let mut obj = MaybeUninit::uninit();
-    let res = unsafe {
-        load_plugin(
-            CString::new(lib.trim()).unwrap().as_c_str().into(),
-            CString::new(args.trim()).unwrap().as_c_str().into(),
-            &mut obj,
-        )
-    };
-    let mut obj = unsafe { from_int_result::<_, InputFlowError>(res, obj) }?;
-
-    {
-        let mut borrowed = obj.borrow_features();
-
-        if let Some(features) = FeatureSupport::from_bits(borrowed.capabilities()) {
-            println!("loaded {} with features: {:?}", borrowed.name(), features);
-
-            // borrow a generic trait object of type &mut (impl Loadable + MouseWriter)
-            if let Some(obj) = as_mut!(borrowed impl MouseWriter) {
-                println!("Using borrowed mouse:");
-                obj.send_button_down(MouseButton::Left)?;
-                obj.send_button_up(MouseButton::Left)?;
-            }
+    let mut handle = inputflow::load(lib.trim(), args.trim())?;
 
-            if let Some(obj) = as_mut!(borrowed impl MouseWriter) {
-                println!("clearing buttons:");
-                obj.clear_buttons()?;
-            }
-        } else {
-            println!(
-                "ERROR: Some features were not valid in bytes: {:#b}",
-                borrowed.capabilities()
-            );
-        }
+    println!(
+        "loaded {} with features: {:?}",
+        handle.name(),
+        handle.features()
+    );
 
-        println!("Borrowed done.");
+    // typed accessors return None when the plugin lacks the trait
+    if let Some(mouse) = handle.mouse() {
+        println!("Using mouse:");
+        mouse.send_button_down(MouseButton::Left)?;
+        mouse.send_button_up(MouseButton::Left)?;
     }
 
-    {
-        let mut owned = obj.into_features();
-
-        if let Some(obj) = as_mut!(owned impl MouseWriter) {
-            println!("Using owned MouseWriter:");
-
-            //click once
-            let mut click_result = obj.click_button(MouseButton::Left);
+    if let Some(reader) = handle.mouse_reader() {
+        println!(
+            "Left button down: {:?}",
+            reader.is_button_down(MouseButton::Left)
+        );
+    }
 
-            println!("Click Result: {click_result:?}");
+    if let Some(mouse) = handle.mouse_absolute() {
+        println!("Screen bounds: {:?}", mouse.screen_bounds());
+        println!("Centering mouse:");
+        mouse.mouse_move_absolute(0.5, 0.5)?;
+    }
 
-            click_result = obj.send_button_down(MouseButton::Left);
-            println!("Press Result: {click_result:?}");
-            // wiggle
-            let scale = 5;
-            // wigg the mouse out for a few seconds
-            for i in 0..1000 {
-                let x = (i % (5 * scale)) - 2 * scale;
-                let y = (i - 2) % (7 * scale) - 3 * scale;
-                obj.mouse_move_relative(x, y)?;
-                std::thread::sleep(Duration::from_millis(4));
-            }
-            click_result = obj.send_button_up(MouseButton::Left);
-            println!("Release Result: {click_result:?}");
+    // batches the events natively where supported, falls back to the writer traits otherwise
+    println!("Sending event batch:");
+    let batch_result = handle.send_events(&[
+        InputEvent::MoveRelative { x: 10, y: 10 },
+        InputEvent::MoveRelative { x: -10, y: -10 },
+    ]);
+    println!("Batch Result: {batch_result:?}");
+
+    if let Some(mouse) = handle.mouse() {
+        //click once
+        let mut click_result = mouse.click_button(MouseButton::Left);
+
+        println!("Click Result: {click_result:?}");
+
+        click_result = mouse.send_button_down(MouseButton::Left);
+        println!("Press Result: {click_result:?}");
+        // wiggle
+        let scale = 5;
+        // wigg the mouse out for a few seconds
+        for i in 0..1000 {
+            let x = (i % (5 * scale)) - 2 * scale;
+            let y = (i - 2) % (7 * scale) - 3 * scale;
+            mouse.mouse_move_relative(x, y)?;
+            std::thread::sleep(Duration::from_millis(4));
         }
+        click_result = mouse.send_button_up(MouseButton::Left);
+        println!("Release Result: {click_result:?}");
 
-        // Casting can be combined with a multiple of optional traits.
-        if let Some(mut obj) = cast!(owned impl MouseWriter + KeyboardWriter) {
-            println!("Clearing keyboard keys");
-            obj.clear_keys()?;
-            // You can still use the mandatory traits.
-            obj.name();
-        }
+        println!("clearing buttons:");
+        mouse.clear_buttons()?;
+    }
 
-        println!("Owned done.");
+    // Casting can be combined with a multiple of optional traits.
+    let owned = handle.into_features();
+    if let Some(mut obj) = cast!(owned impl MouseWriter + KeyboardWriter) {
+        println!("Clearing keyboard keys");
+        obj.clear_keys()?;
+        // You can still use the mandatory traits.
+        obj.name();
     }
 
     println!("Quitting");
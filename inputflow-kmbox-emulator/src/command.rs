@@ -0,0 +1,122 @@
+//! Parsing the `km.*` calls the host types into the interpreter.
+
+/// A call of a `km` module function with integer arguments, e.g. `km.move(10,-5)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Call<'a> {
+    /// Function name without the `km.` prefix
+    pub name: &'a str,
+    pub args: Vec<i64>,
+}
+
+impl Call<'_> {
+    /// Fails with the python `TypeError` if the argument count is not one of `counts`
+    pub fn expect_args(&self, counts: &[usize]) -> Result<(), String> {
+        let given = self.args.len();
+        if counts.contains(&given) {
+            return Ok(());
+        }
+        Err(match counts {
+            [count] => {
+                format!(
+                    "TypeError: function takes {count} positional arguments but {given} were given"
+                )
+            }
+            [first @ .., last] => {
+                let first: Vec<_> = first.iter().map(usize::to_string).collect();
+                format!(
+                    "TypeError: function expected {} or {last} arguments, got {given}",
+                    first.join(", ")
+                )
+            }
+            [] => format!("TypeError: function takes no arguments but {given} were given"),
+        })
+    }
+}
+
+/// Parses a line such as `km.move(10, -5)`, failing with the python exception the device would print
+pub(crate) fn parse(line: &str) -> Result<Call<'_>, String> {
+    const SYNTAX_ERROR: &str = "SyntaxError: invalid syntax";
+
+    let (target, args) = line
+        .strip_suffix(')')
+        .and_then(|call| call.split_once('('))
+        .ok_or(SYNTAX_ERROR)?;
+
+    let target = target.trim();
+    let Some(name) = target.strip_prefix("km.") else {
+        let module = target.split('.').next().unwrap_or_default();
+        return Err(if is_identifier(module) {
+            format!("NameError: name '{module}' isn't defined")
+        } else {
+            SYNTAX_ERROR.to_string()
+        });
+    };
+    if !is_identifier(name) {
+        return Err(SYNTAX_ERROR.to_string());
+    }
+
+    let args = args.trim();
+    let args = if args.is_empty() {
+        Vec::new()
+    } else {
+        // python accepts a trailing comma
+        args.strip_suffix(',')
+            .unwrap_or(args)
+            .split(',')
+            .map(|arg| arg.trim().parse().map_err(|_| SYNTAX_ERROR.to_string()))
+            .collect::<Result<_, _>>()?
+    };
+
+    Ok(Call { name, args })
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls() {
+        assert_eq!(
+            parse("km.move(10, -5)").unwrap(),
+            Call {
+                name: "move",
+                args: vec![10, -5]
+            }
+        );
+        assert!(parse("km.left()").unwrap().args.is_empty());
+        assert_eq!(parse("km.wheel(1,)").unwrap().args, [1]);
+    }
+
+    #[test]
+    fn invalid_calls() {
+        assert!(parse("km.move").unwrap_err().starts_with("SyntaxError"));
+        assert!(parse("km.move(1,,2)")
+            .unwrap_err()
+            .starts_with("SyntaxError"));
+        assert!(parse("km.2move(1)").unwrap_err().starts_with("SyntaxError"));
+        assert_eq!(
+            parse("kmx.move(1)").unwrap_err(),
+            "NameError: name 'kmx' isn't defined"
+        );
+    }
+
+    #[test]
+    fn argument_counts() {
+        let call = parse("km.move(1,2,3,4)").unwrap();
+        assert!(call.expect_args(&[4]).is_ok());
+        assert_eq!(
+            call.expect_args(&[1]).unwrap_err(),
+            "TypeError: function takes 1 positional arguments but 4 were given"
+        );
+        assert_eq!(
+            call.expect_args(&[2, 3, 7]).unwrap_err(),
+            "TypeError: function expected 2, 3 or 7 arguments, got 4"
+        );
+    }
+}
@@ -0,0 +1,521 @@
+//! Emulates a KMBox serial device for testing `inputflow-kmbox` without hardware.
+//!
+//! [`Emulator`] interprets `km.*` commands like the python interpreter running on the device,
+//! [`serve`] runs it on a byte stream such as a pty or a TCP connection.
+//! Tests can simulate the physical mouse and keyboard plugged into the device, a device that
+//! fails in different ways, and unplugging it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+mod command;
+
+use command::Call;
+
+/// Prompt the device prints once it is ready for the next command
+pub const PROMPT: &str = ">>> ";
+
+/// Simulated state of the computer the KMBox is plugged into
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceState {
+    /// Sum of all relative moves since the emulator started
+    pub cursor: Cursor,
+    /// Sum of all wheel notches, positive is up
+    pub wheel: i64,
+    pub buttons: Buttons,
+    /// HID usage codes of the keys held down
+    pub keys: BTreeSet<u8>,
+    /// `km.click` count per button name
+    pub clicks: BTreeMap<String, u64>,
+    /// `km.press` count per HID usage code
+    pub key_presses: BTreeMap<u8, u64>,
+    /// Number of commands run, including failed ones
+    pub commands: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Cursor {
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Which mouse buttons are held down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Buttons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+    pub side1: bool,
+    pub side2: bool,
+}
+
+impl Buttons {
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "left" => &mut self.left,
+            "right" => &mut self.right,
+            "middle" => &mut self.middle,
+            "side1" => &mut self.side1,
+            "side2" => &mut self.side2,
+            _ => return None,
+        })
+    }
+}
+
+/// Button names in the order `km.click` numbers them, also the bits of [`Emulator::physical_mouse`]
+const CLICK_BUTTONS: [&str; 5] = ["left", "right", "middle", "side1", "side2"];
+
+/// How the emulated device answers commands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Behavior {
+    /// Runs commands like a KMBox
+    #[default]
+    Kmbox,
+    /// Never answers, like a hung device or a wrong baud rate
+    Silent,
+    /// Answers every command with a python exception, like a device without the `km` module
+    Rejecting,
+}
+
+/// What the device prints for one line of input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    /// Echo of the line, the output of the command and the prompt
+    pub text: String,
+    /// How long the command keeps the device busy before the reply is complete, e.g. timed moves
+    pub busy: Duration,
+}
+
+/// The python interpreter of a KMBox, reduced to the `km` module
+#[derive(Debug, Default)]
+pub struct Emulator {
+    state: DeviceState,
+    behavior: Behavior,
+    /// everything the host sent since the last [`Emulator::take_received`]
+    received: String,
+    /// set by [`Emulator::unplug`] until [`serve`] returns
+    unplugged: bool,
+    /// names of the buttons the physical mouse cannot press on the target
+    masked_buttons: BTreeSet<&'static str>,
+    /// HID usage codes of the keys the physical keyboard cannot press on the target
+    masked_keys: BTreeSet<u8>,
+    /// button bits of the physical mouse, see [`Emulator::physical_mouse`]
+    physical_buttons: u8,
+    /// HID usage codes held on the physical keyboard, modifiers as `0xE0` to `0xE7`
+    physical_keys: BTreeSet<u8>,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    pub fn set_behavior(&mut self, behavior: Behavior) {
+        self.behavior = behavior;
+    }
+
+    /// Everything the host sent since the last call, line endings included
+    pub fn take_received(&mut self) -> String {
+        std::mem::take(&mut self.received)
+    }
+
+    /// Simulates pulling the cable: [`serve`] stops at its next read and returns an error,
+    /// dropping the stream afterwards closes it on the host
+    pub fn unplug(&mut self) {
+        self.unplugged = true;
+    }
+
+    /// Simulates the physical mouse holding `buttons`, with the bits left, right, middle, side1 and side2.
+    ///
+    /// Buttons that changed reach the target unless they are masked.
+    /// `km.left()`, `km.right()`, ... answer with the physical state either way.
+    pub fn physical_mouse(&mut self, buttons: u8) {
+        for (bit, name) in CLICK_BUTTONS.into_iter().enumerate() {
+            let mask = 1 << bit;
+            if (self.physical_buttons ^ buttons) & mask != 0 && !self.masked_buttons.contains(name)
+            {
+                *self.state.buttons.get_mut(name).expect("click button name") = buttons & mask != 0;
+            }
+        }
+        self.physical_buttons = buttons;
+    }
+
+    /// Simulates the physical keyboard holding `keys`, HID usage codes with the modifiers as `0xE0` to `0xE7`.
+    ///
+    /// Keys that changed reach the target unless they are masked.
+    /// `km.isdown` answers with the physical state either way.
+    pub fn physical_keyboard(&mut self, keys: &[u8]) {
+        let held: BTreeSet<u8> = keys.iter().copied().collect();
+        for &code in self.physical_keys.symmetric_difference(&held) {
+            if self.masked_keys.contains(&code) {
+                continue;
+            }
+            if held.contains(&code) {
+                self.state.keys.insert(code);
+            } else {
+                self.state.keys.remove(&code);
+            }
+        }
+        self.physical_keys = held;
+    }
+
+    /// The state as a single line of JSON
+    pub fn state_json(&self) -> String {
+        serde_json::to_string(&self.state).expect("device state is always serializable")
+    }
+
+    /// Runs one line the host sent, without its line ending, and returns what the device prints back
+    pub fn handle_line(&mut self, line: &str) -> Reply {
+        let line = line.trim();
+        if self.behavior == Behavior::Silent {
+            log::trace!("ignoring {line:?}");
+            return Reply {
+                text: String::new(),
+                busy: Duration::ZERO,
+            };
+        }
+
+        let mut text = format!("{line}\r\n");
+        let mut busy = Duration::ZERO;
+
+        if !line.is_empty() {
+            self.state.commands += 1;
+            let result = match self.behavior {
+                Behavior::Rejecting => Err("NameError: name 'km' isn't defined".to_string()),
+                _ => command::parse(line).and_then(|call| self.execute(&call, &mut busy)),
+            };
+            match result {
+                Ok(Some(value)) => text.push_str(&format!("{value}\r\n")),
+                Ok(None) => {}
+                Err(error) => {
+                    log::debug!("{line:?} failed: {error}");
+                    text.push_str(&format!(
+                        "Traceback (most recent call last):\r\n  File \"<stdin>\", line 1, in <module>\r\n{error}\r\n"
+                    ));
+                }
+            }
+        }
+
+        text.push_str(PROMPT);
+        Reply { text, busy }
+    }
+
+    /// Runs a parsed `km.*` call, returning the value the interpreter prints or the python exception
+    fn execute(&mut self, call: &Call, busy: &mut Duration) -> Result<Option<i64>, String> {
+        let state = &mut self.state;
+        let args = &call.args[..];
+
+        match call.name {
+            // with four more arguments the move follows a bezier curve through two control points,
+            // which only changes the path and not where the cursor ends up
+            "move" => {
+                call.expect_args(&[2, 3, 7])?;
+                state.cursor.x += args[0];
+                state.cursor.y += args[1];
+                *busy = duration_arg(args.get(2))?;
+            }
+            // without an argument the button of the physical mouse is queried, masked or not
+            name @ ("left" | "right" | "middle" | "side1" | "side2") => {
+                call.expect_args(&[0, 1])?;
+                let Some(down) = args.first() else {
+                    let bit = CLICK_BUTTONS.iter().position(|b| *b == name);
+                    let mask = 1 << bit.expect("matched button name");
+                    return Ok(Some((self.physical_buttons & mask != 0).into()));
+                };
+                *state.buttons.get_mut(name).expect("matched button name") = *down != 0;
+            }
+            "wheel" => {
+                call.expect_args(&[1])?;
+                state.wheel += args[0];
+            }
+            "click" => {
+                call.expect_args(&[1])?;
+                let name = usize::try_from(args[0])
+                    .ok()
+                    .and_then(|i| CLICK_BUTTONS.get(i))
+                    .ok_or_else(|| format!("ValueError: no mouse button {}", args[0]))?;
+                *state.clicks.entry(name.to_string()).or_default() += 1;
+            }
+            "down" => {
+                call.expect_args(&[1])?;
+                state.keys.insert(key_arg(args[0])?);
+            }
+            "up" => {
+                call.expect_args(&[1])?;
+                state.keys.remove(&key_arg(args[0])?);
+            }
+            "press" => {
+                call.expect_args(&[1, 2, 3])?;
+                let key = key_arg(args[0])?;
+                // the key is held for the given time, the random extra time is left out to stay deterministic
+                *busy = duration_arg(args.get(1))?;
+                *state.key_presses.entry(key).or_default() += 1;
+            }
+            // queries the physical keyboard, masked or not
+            "isdown" => {
+                call.expect_args(&[1])?;
+                let key = key_arg(args[0])?;
+                return Ok(Some(self.physical_keys.contains(&key).into()));
+            }
+            "mask_left" | "mask_right" | "mask_middle" | "mask_side1" | "mask_side2" => {
+                call.expect_args(&[1])?;
+                let name = CLICK_BUTTONS
+                    .into_iter()
+                    .find(|name| call.name.ends_with(name))
+                    .expect("matched button name");
+                if args[0] != 0 {
+                    self.masked_buttons.insert(name);
+                } else {
+                    self.masked_buttons.remove(name);
+                }
+            }
+            // the physical mouse never moves
+            "mask_x" | "mask_y" | "mask_wheel" => call.expect_args(&[1])?,
+            "mask_keyboard" => {
+                call.expect_args(&[1])?;
+                self.masked_keys.insert(key_arg(args[0])?);
+            }
+            "unmask_keyboard" => {
+                call.expect_args(&[1])?;
+                self.masked_keys.remove(&key_arg(args[0])?);
+            }
+            "unmask_all" => {
+                call.expect_args(&[0])?;
+                self.masked_buttons.clear();
+                self.masked_keys.clear();
+            }
+            name => {
+                return Err(format!(
+                    "AttributeError: 'module' object has no attribute '{name}'"
+                ))
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn key_arg(value: i64) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("ValueError: invalid key code {value}"))
+}
+
+fn duration_arg(value: Option<&i64>) -> Result<Duration, String> {
+    let Some(&ms) = value else {
+        return Ok(Duration::ZERO);
+    };
+    u64::try_from(ms)
+        .map(Duration::from_millis)
+        .map_err(|_| format!("ValueError: negative duration {ms}"))
+}
+
+/// Runs `emulator` on `io` until reading or writing fails, e.g. because the peer went away,
+/// or until [`Emulator::unplug`] is called.
+///
+/// Read timeouts are retried, so the emulator notices [`Emulator::unplug`] if `io` has a read timeout.
+/// `on_command` is called with the state after every line.
+pub fn serve<T: Read + Write + ?Sized>(
+    emulator: &Mutex<Emulator>,
+    io: &mut T,
+    mut on_command: impl FnMut(&DeviceState),
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    let mut buf = [0u8; 256];
+
+    loop {
+        if std::mem::take(&mut emulator.lock().unwrap().unplugged) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the emulated device was unplugged",
+            ));
+        }
+
+        let n = match io.read(&mut buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        };
+        emulator
+            .lock()
+            .unwrap()
+            .received
+            .push_str(&String::from_utf8_lossy(&buf[..n]));
+
+        for &byte in &buf[..n] {
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+
+            let command = String::from_utf8_lossy(&line).into_owned();
+            line.clear();
+            log::trace!("received {command:?}");
+
+            let reply = {
+                let mut emulator = emulator.lock().unwrap();
+                let reply = emulator.handle_line(&command);
+                on_command(emulator.state());
+                reply
+            };
+            std::thread::sleep(reply.busy);
+            io.write_all(reply.text.as_bytes())?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(emulator: &mut Emulator, line: &str) -> String {
+        emulator.handle_line(line).text
+    }
+
+    #[test]
+    fn echo_and_prompt() {
+        let mut emulator = Emulator::new();
+        assert_eq!(
+            run(&mut emulator, "km.move(10,-5)"),
+            "km.move(10,-5)\r\n>>> "
+        );
+        assert_eq!(run(&mut emulator, "km.left()\r"), "km.left()\r\n0\r\n>>> ");
+        assert_eq!(run(&mut emulator, ""), "\r\n>>> ");
+        assert_eq!(emulator.state().cursor, Cursor { x: 10, y: -5 });
+        assert_eq!(emulator.state().commands, 2);
+    }
+
+    #[test]
+    fn buttons_and_keys() {
+        let mut emulator = Emulator::new();
+        run(&mut emulator, "km.right(1)");
+        run(&mut emulator, "km.down(4)");
+        run(&mut emulator, "km.down(5)");
+        run(&mut emulator, "km.up(5)");
+        run(&mut emulator, "km.press(6,15,50)");
+        run(&mut emulator, "km.click(2)");
+        run(&mut emulator, "km.wheel(-1)");
+
+        let state = emulator.state();
+        assert!(state.buttons.right && !state.buttons.left);
+        assert_eq!(state.keys, BTreeSet::from([4]));
+        assert_eq!(state.key_presses, BTreeMap::from([(6, 1)]));
+        assert_eq!(state.clicks, BTreeMap::from([("middle".to_string(), 1)]));
+        assert_eq!(state.wheel, -1);
+    }
+
+    #[test]
+    fn timed_commands_keep_the_device_busy() {
+        let mut emulator = Emulator::new();
+        assert_eq!(
+            emulator.handle_line("km.move(100,0,30)").busy,
+            Duration::from_millis(30)
+        );
+        assert_eq!(
+            emulator.handle_line("km.move(1,2,40,0,0,0,0)").busy,
+            Duration::from_millis(40)
+        );
+        assert_eq!(emulator.state().cursor, Cursor { x: 101, y: 2 });
+    }
+
+    #[test]
+    fn errors_print_a_traceback() {
+        let mut emulator = Emulator::new();
+        let reply = run(&mut emulator, "km.bogus(1)");
+        assert!(reply.starts_with("km.bogus(1)\r\nTraceback (most recent call last):\r\n"));
+        assert!(reply.ends_with("AttributeError: 'module' object has no attribute 'bogus'\r\n>>> "));
+
+        for (line, error) in [
+            ("km.left(1,2)", "TypeError"),
+            ("km.move(1,2,3,4)", "TypeError"),
+            ("km.bezier(1,2,40,0,0,0,0)", "AttributeError"),
+            ("km.down(300)", "ValueError"),
+            ("km.click(9)", "ValueError"),
+            ("km.move(1", "SyntaxError"),
+            ("km.move(a,b)", "SyntaxError"),
+            ("mouse.move(1,1)", "NameError"),
+        ] {
+            assert!(run(&mut emulator, line).contains(error), "{line}");
+        }
+        assert_eq!(
+            emulator.state(),
+            &DeviceState {
+                commands: 9,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn physical_input_is_masked_and_queried() {
+        let mut emulator = Emulator::new();
+        emulator.physical_mouse(0x02);
+        emulator.physical_keyboard(&[0x04]);
+        assert!(emulator.state().buttons.right);
+        assert_eq!(emulator.state().keys, BTreeSet::from([0x04]));
+
+        run(&mut emulator, "km.mask_left(1)");
+        run(&mut emulator, "km.mask_keyboard(5)");
+        emulator.physical_mouse(0x01);
+        emulator.physical_keyboard(&[0xE1, 0x05, 0x04]);
+        assert!(!emulator.state().buttons.left && !emulator.state().buttons.right);
+        assert_eq!(emulator.state().keys, BTreeSet::from([0x04, 0xE1]));
+
+        // queries answer with the physical input, not with what reached the target
+        assert_eq!(run(&mut emulator, "km.left()"), "km.left()\r\n1\r\n>>> ");
+        assert_eq!(run(&mut emulator, "km.right()"), "km.right()\r\n0\r\n>>> ");
+        assert_eq!(
+            run(&mut emulator, "km.isdown(5)"),
+            "km.isdown(5)\r\n1\r\n>>> "
+        );
+        run(&mut emulator, "km.down(6)");
+        assert_eq!(
+            run(&mut emulator, "km.isdown(6)"),
+            "km.isdown(6)\r\n0\r\n>>> "
+        );
+
+        run(&mut emulator, "km.unmask_all()");
+        emulator.physical_mouse(0x00);
+        emulator.physical_keyboard(&[]);
+        assert_eq!(emulator.state().keys, BTreeSet::from([0x06]));
+    }
+
+    #[test]
+    fn failing_devices() {
+        let mut emulator = Emulator::new();
+        emulator.set_behavior(Behavior::Silent);
+        assert_eq!(run(&mut emulator, "km.left(1)"), "");
+
+        emulator.set_behavior(Behavior::Rejecting);
+        let reply = run(&mut emulator, "km.left(1)");
+        assert!(reply.ends_with("NameError: name 'km' isn't defined\r\n>>> "));
+        assert!(!emulator.state().buttons.left);
+        assert_eq!(emulator.state().commands, 1);
+    }
+
+    #[test]
+    fn state_json() {
+        let mut emulator = Emulator::new();
+        run(&mut emulator, "km.left(1)");
+        run(&mut emulator, "km.press(4)");
+        assert_eq!(
+            emulator.state_json(),
+            r#"{"cursor":{"x":0,"y":0},"wheel":0,"buttons":{"left":true,"right":false,"middle":false,"side1":false,"side2":false},"keys":[],"clicks":{},"key_presses":{"4":1},"commands":2}"#
+        );
+    }
+}
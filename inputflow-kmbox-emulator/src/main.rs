@@ -0,0 +1,138 @@
+//! Emulates a KMBox on a pty or a TCP port so `inputflow-kmbox` can be exercised without hardware.
+//!
+//! The pty path or the TCP address is printed on the first line of stdout, every line entered on
+//! stdin prints the simulated state as JSON.
+
+use std::io::{self, BufRead};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use inputflow_kmbox_emulator::{serve, DeviceState, Emulator};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "usage: inputflow-kmbox-emulator [--pty | --tcp <host:port>] [--state <file>]
+
+  --pty            creates a pseudo terminal and prints its path (default)
+  --tcp <addr>     listens for tcp connections on addr, e.g. 127.0.0.1:5555
+  --state <file>   writes the simulated state as JSON to file after every command";
+
+/// Where the emulator waits for the plugin
+enum Listen {
+    Pty,
+    Tcp(String),
+}
+
+struct Options {
+    listen: Listen,
+    state_file: Option<PathBuf>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options {
+        listen: Listen::Pty,
+        state_file: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pty" => options.listen = Listen::Pty,
+            "--tcp" => options.listen = Listen::Tcp(args.next().ok_or("--tcp needs an address")?),
+            "--state" => {
+                options.state_file = Some(args.next().ok_or("--state needs a file")?.into())
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {arg:?}\n{USAGE}").into()),
+        }
+    }
+    Ok(options)
+}
+
+fn main() -> Result<()> {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let options = parse_options()?;
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+
+    // dump the state on demand
+    {
+        let emulator = emulator.clone();
+        std::thread::spawn(move || {
+            for _ in io::stdin().lock().lines() {
+                println!("{}", emulator.lock().unwrap().state_json());
+            }
+        });
+    }
+
+    let state_file = options.state_file;
+    let on_command = |state: &DeviceState| {
+        if let Some(path) = &state_file {
+            if let Err(e) = write_state(path, state) {
+                log::warn!("writing state to {} failed: {e}", path.display());
+            }
+        }
+    };
+
+    match options.listen {
+        Listen::Pty => serve_pty(&emulator, on_command),
+        Listen::Tcp(addr) => serve_tcp(&emulator, &addr, on_command),
+    }
+}
+
+#[cfg(unix)]
+fn serve_pty(emulator: &Mutex<Emulator>, on_command: impl FnMut(&DeviceState)) -> Result<()> {
+    use serialport::SerialPort;
+
+    let (mut master, slave) = serialport::TTYPort::pair()?;
+    let path = slave.name().ok_or("the pty has no path")?;
+    println!("{path}");
+    log::info!("emulating a kmbox on {path}");
+
+    // holding the slave end open keeps the pty alive while the plugin reconnects
+    let _slave = slave;
+    serve(emulator, &mut master, on_command)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_pty(_emulator: &Mutex<Emulator>, _on_command: impl FnMut(&DeviceState)) -> Result<()> {
+    Err("pseudo terminals are only supported on unix, use --tcp".into())
+}
+
+fn serve_tcp(
+    emulator: &Mutex<Emulator>,
+    addr: &str,
+    mut on_command: impl FnMut(&DeviceState),
+) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    println!("{addr}");
+    log::info!("emulating a kmbox on tcp {addr}");
+
+    // one plugin at a time, the state carries over between connections
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let peer = stream.peer_addr()?;
+        stream.set_nodelay(true)?;
+        log::info!("{peer} connected");
+        let result = serve(emulator, &mut stream, &mut on_command);
+        log::info!("{peer} disconnected: {result:?}");
+    }
+    Ok(())
+}
+
+/// Replaces `path` with the state, through a temporary file so readers never see half of it
+fn write_state(path: &Path, state: &DeviceState) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, serde_json::to_string(state)?)?;
+    std::fs::rename(&tmp, path)
+}
@@ -1,83 +1,46 @@
 // Arguments for the plugin initialization
 
-use ::std::error::Error;
-
-// default config values for each platform
-
-/// Returns the default value for serial port name or path
-/// on windows this is generally `COM#`
-/// on unix this is generally a path like `/dev/ttyUSB#`
-fn default_com_port() -> String {
-    #[cfg(target_family="unix")]
-    {"/dev/ttyUSB0".to_string()}
-    #[cfg(target_family="windows")]
-    {"COM6".to_string()}
-}
-
-/// Returns the default serial device name to look for
-/// this is used when auto port find mode is enabled instead
-/// specifying of manual com path
-fn default_device_name() -> String {
-    "USB-SERIAL CH340".to_string()
-}
-
-/// Auto port selection by device name is the default behaviour
-fn default_auto_select() -> bool {
-    true
-}
-
-/// Default serial baud rate is the default of KMBox:
-/// one hundred and fifteen thousand, two hundred.
-fn default_baud_rate() -> u32 {
-    115_200
-}
-
-#[derive(::serde::Serialize, ::serde::Deserialize)]
-pub(crate) struct Args {
-    /// Automatically selects the serial port
-    /// based on the device name argument if true
-    #[serde(default = "default_auto_select")]
-    pub auto_select: bool,
-    /// The device name to search for in auto mode.
-    /// defaults to `USB-SERIAL CH340`.
-    #[serde(default = "default_device_name")]
-    pub device_name: String,
-    /// The serial port path to connect to (used when auto_select is false)
-    /// defaults to `COM6` on Windows and `/dev/ttyUSB0` on Unix
-    #[serde(default = "default_com_port")]
-    pub com_port: String,
-    #[serde(default = "default_baud_rate")]
-    pub baud_rate: u32,
-    /// Port timeout value in milliseconds.
-    /// Defaults to zero (no timeout)
-    #[serde(default)]
-    pub timeout_ms: u64,
-}
-
-impl Default for Args {
-    fn default() -> Self {
-        Self {
-            auto_select: true,
-            device_name: "USB-SERIAL CH340".to_string(),
-            com_port: "COM6".to_string(),
-            baud_rate: 115200,
-            timeout_ms: Default::default()
-        }
+inputflow::define_args! {
+    #[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+    pub(crate) struct Args {
+        /// Automatically selects the serial port by usb ids, serial number or device name if true
+        auto_select: bool = true,
+        /// The USB product name prefix to search for in auto mode,
+        /// used when no vid, pid or serial_number is given
+        device_name: String = "USB-SERIAL CH340",
+        /// USB vendor id to search for in auto mode, e.g. 0x1a86
+        vid: Option<u16> = None,
+        /// USB product id to search for in auto mode, e.g. 0x7523
+        pid: Option<u16> = None,
+        /// USB serial number to search for in auto mode
+        serial_number: Option<String> = None,
+        /// Picks the n-th matching device in auto mode when several are connected, sorted by port name
+        port_index: u32 = 0,
+        /// The serial port path to connect to (used when auto_select is false)
+        #[cfg(target_family = "unix")]
+        com_port: String = "/dev/ttyUSB0",
+        /// The serial port path to connect to (used when auto_select is false)
+        #[cfg(target_family = "windows")]
+        com_port: String = "COM6",
+        /// host:port of a serial to network bridge or emulator,
+        /// connects over tcp instead of serial if set
+        tcp_addr: Option<String> = None,
+        /// Serial baud rate of the KMBox
+        baud_rate: u32 = 115200,
+        /// Serial port timeout in milliseconds, zero fails right away if the port is not ready.
+        /// While a response is due, reads wait at most 10ms at a time
+        /// and response_timeout_ms decides when to give up
+        timeout_ms: u64 = 0,
+        /// How long the KMBox may take to acknowledge a command before it counts as failed
+        response_timeout_ms: u64 = 100,
+        /// Sends mouse moves without waiting for the acknowledgement,
+        /// lower latency but errors go unnoticed until the next acknowledged command
+        fire_and_forget_moves: bool = false,
+        /// IP address of a KMBox Net, connects over the network instead of serial if set
+        net_ip: Option<String> = None,
+        /// UDP port of the KMBox Net, shown on its screen
+        net_port: u16 = 0,
+        /// UUID of the KMBox Net as shown on its screen, 8 hex digits
+        net_uuid: String = "",
     }
 }
-
-/// handles the raw string args to extract useful information for initialization
-pub(super) fn parse_args(args: *const std::ffi::c_char)  -> Result<Args, Box<dyn Error>> {
-    // safety:
-    // the plugin caller created this string with into_raw
-    // therefor we must use from_raw on it to make sure it dealocates afterwards
-    // furthermore, we will not be modifying the string or its length
-    let args_str = unsafe{std::ffi::CString::from_raw(args as *mut _)};
-
-    if args_str.is_empty() {
-        Ok(Args::default())
-    } else {
-        log::info!("inputflow_kmbox received args: {args_str:?}");
-        Ok(ron::from_str(args_str.to_str()?)?)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,284 @@
+//! Finding KMBox devices among the USB serial ports of the system.
+
+use inputflow::prelude::*;
+use serialport::{SerialPortType, UsbPortInfo};
+
+use crate::args::Args;
+
+/// A USB serial port that could be a KMBox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KMBoxDevice {
+    /// Path or name to open the port with, e.g. `/dev/ttyUSB0` or `COM6`
+    pub port_name: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+impl std::fmt::Display for KMBoxDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({:04x}:{:04x}", self.port_name, self.vid, self.pid)?;
+        if let Some(product) = &self.product {
+            write!(f, " {product}")?;
+        }
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, " serial {serial_number}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Lists every USB serial port of the system, sorted by port name
+pub fn discover_devices() -> Result<Vec<KMBoxDevice>> {
+    let ports = serialport::available_ports().map_err(|e| {
+        InputFlowError::Io.with_message(format!("listing serial ports failed: {e}"))
+    })?;
+
+    let mut devices: Vec<_> = ports
+        .into_iter()
+        .filter_map(|port| {
+            log::trace!(
+                "Found serial port {} : {:?}",
+                port.port_name,
+                port.port_type
+            );
+            match port.port_type {
+                SerialPortType::UsbPort(UsbPortInfo {
+                    vid,
+                    pid,
+                    serial_number,
+                    manufacturer,
+                    product,
+                    ..
+                }) => Some(KMBoxDevice {
+                    port_name: port.port_name,
+                    vid,
+                    pid,
+                    serial_number,
+                    manufacturer,
+                    product,
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+    devices.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    Ok(devices)
+}
+
+/// Lists every USB serial port as a device the plugin can be created for, exported in the plugin header
+pub(crate) fn device_descriptors() -> Result<Vec<DeviceDescriptor>> {
+    Ok(discover_devices()?
+        .iter()
+        .map(KMBoxDevice::descriptor)
+        .collect())
+}
+
+impl KMBoxDevice {
+    /// Describes the device with the arguments that open its port
+    pub fn descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor {
+            args: format!("auto_select=false,com_port={:?}", self.port_name).into(),
+            description: self.to_string().into(),
+        }
+    }
+}
+
+/// Which devices `auto_select` accepts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    /// Prefix of the USB product name
+    pub product: Option<String>,
+    /// Picks the n-th of the matching devices
+    pub index: usize,
+}
+
+impl DeviceFilter {
+    /// Builds the filter from the plugin arguments.
+    /// The product name only narrows the search if no vid, pid or serial number is given.
+    pub(crate) fn from_args(args: &Args) -> Self {
+        let product = if args.vid.is_none() && args.pid.is_none() && args.serial_number.is_none() {
+            Some(args.device_name.clone()).filter(|s| !s.is_empty())
+        } else {
+            None
+        };
+
+        Self {
+            vid: args.vid,
+            pid: args.pid,
+            serial_number: args.serial_number.clone(),
+            product,
+            index: args.port_index as usize,
+        }
+    }
+
+    pub fn matches(&self, device: &KMBoxDevice) -> bool {
+        self.vid.is_none_or(|vid| vid == device.vid)
+            && self.pid.is_none_or(|pid| pid == device.pid)
+            && self
+                .serial_number
+                .as_ref()
+                .is_none_or(|s| device.serial_number.as_ref() == Some(s))
+            && self.product.as_ref().is_none_or(|p| {
+                device
+                    .product
+                    .as_ref()
+                    .is_some_and(|product| product.starts_with(p.as_str()))
+            })
+    }
+
+    /// Picks the device from `devices`, failing with a list of what was found if none fits
+    pub fn select<'a>(&self, devices: &'a [KMBoxDevice]) -> Result<&'a KMBoxDevice> {
+        let matching: Vec<_> = devices.iter().filter(|d| self.matches(d)).collect();
+        if let Some(device) = matching.get(self.index) {
+            return Ok(device);
+        }
+
+        let found = if devices.is_empty() {
+            "no usb serial devices".to_string()
+        } else {
+            devices
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let message = if matching.is_empty() {
+            format!("no kmbox matching {self} found, available: {found}")
+        } else {
+            format!(
+                "port_index {} is out of range, only {} kmbox devices match {self}: {found}",
+                self.index,
+                matching.len()
+            )
+        };
+        log::error!("{message}.");
+        Err(InputFlowError::Io.with_message(message))
+    }
+}
+
+impl std::fmt::Display for DeviceFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(vid) = self.vid {
+            parts.push(format!("vid {vid:04x}"));
+        }
+        if let Some(pid) = self.pid {
+            parts.push(format!("pid {pid:04x}"));
+        }
+        if let Some(serial_number) = &self.serial_number {
+            parts.push(format!("serial {serial_number:?}"));
+        }
+        if let Some(product) = &self.product {
+            parts.push(format!("product {product:?}"));
+        }
+        if parts.is_empty() {
+            write!(f, "any device")
+        } else {
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(
+        port_name: &str,
+        vid: u16,
+        pid: u16,
+        serial: Option<&str>,
+        product: &str,
+    ) -> KMBoxDevice {
+        KMBoxDevice {
+            port_name: port_name.to_string(),
+            vid,
+            pid,
+            serial_number: serial.map(Into::into),
+            manufacturer: None,
+            product: Some(product.to_string()),
+        }
+    }
+
+    fn devices() -> Vec<KMBoxDevice> {
+        vec![
+            device("/dev/ttyUSB0", 0x1a86, 0x7523, None, "USB-SERIAL CH340"),
+            device(
+                "/dev/ttyUSB1",
+                0x1a86,
+                0x55d3,
+                Some("A1"),
+                "USB Single Serial",
+            ),
+            device(
+                "/dev/ttyUSB2",
+                0x1a86,
+                0x7523,
+                Some("B2"),
+                "USB-SERIAL CH340",
+            ),
+        ]
+    }
+
+    fn parse(args: &str) -> Result<Args> {
+        Ok(ConnectorArgs::parse(args)?.parse_into(crate::args::ARG_HELP)?)
+    }
+
+    fn filter(args: &str) -> DeviceFilter {
+        DeviceFilter::from_args(&parse(args).unwrap())
+    }
+
+    fn select(args: &str) -> Result<String> {
+        filter(args).select(&devices()).map(|d| d.port_name.clone())
+    }
+
+    #[test]
+    fn default_selects_first_ch340() {
+        assert_eq!(select("").unwrap(), "/dev/ttyUSB0");
+        assert_eq!(select("port_index=1").unwrap(), "/dev/ttyUSB2");
+    }
+
+    #[test]
+    fn usb_ids_replace_the_product_name() {
+        assert_eq!(select("vid=0x1a86,pid=0x55d3").unwrap(), "/dev/ttyUSB1");
+        assert_eq!(select("pid=0x7523,port_index=1").unwrap(), "/dev/ttyUSB2");
+        assert_eq!(select("serial_number=B2").unwrap(), "/dev/ttyUSB2");
+    }
+
+    #[test]
+    fn nothing_matching_fails() {
+        assert_eq!(select("vid=0xdead"), Err(InputFlowError::Io));
+        assert!(take_last_error().unwrap().contains("/dev/ttyUSB1"));
+        assert_eq!(select("port_index=2"), Err(InputFlowError::Io));
+        assert_eq!(filter("").select(&[]).map(|_| ()), Err(InputFlowError::Io));
+    }
+
+    #[test]
+    fn descriptors_select_their_port() {
+        let device = &devices()[1];
+        let descriptor = device.descriptor();
+        assert_eq!(
+            descriptor.description.as_str(),
+            "/dev/ttyUSB1 (1a86:55d3 USB Single Serial serial A1)"
+        );
+
+        let args = parse(descriptor.args.as_str()).unwrap();
+        assert!(!args.auto_select);
+        assert_eq!(args.com_port, device.port_name);
+    }
+
+    #[test]
+    fn usb_ids_are_integers() {
+        assert_eq!(filter("vid=0x1A86").vid, Some(0x1a86));
+        assert_eq!(filter("(vid: 6790, pid: 0x7523)").pid, Some(0x7523));
+        assert_eq!(filter("").vid, None);
+        assert_eq!(parse("vid=0x12345").err(), Some(InputFlowError::Parameter));
+        assert_eq!(parse("vid=ch340").err(), Some(InputFlowError::Parameter));
+    }
+}
@@ -1,10 +1,9 @@
 use format_bytes::{write_bytes, DisplayBytes};
 use inputflow::{error::InputFlowError, key_types::KeyboardKey};
 
-
 /// This enum represents which keyboard keys map to which integer for the KMBox api
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum KMBoxKeyboardKeyCode {
     None = 0x00,
     ErrorRollover = 0x01,
@@ -227,17 +226,16 @@ pub enum KMBoxKeyboardKeyCode {
     RightGui = 0xE7,
 }
 
-/// Conversion function from inputflow key to KMBox keycode
-/// currently incomplete.
+/// Conversion function from inputflow key to the KMBox (USB HID keyboard page) keycode.
+///
+/// Keys are mapped by their position on a US keyboard, the same way windows maps virtual keys to scancodes.
+/// Keys without a HID usage, such as the browser and launch keys, fail with [`InputFlowError::InvalidKey`].
 impl TryFrom<KeyboardKey> for KMBoxKeyboardKeyCode {
     type Error = InputFlowError;
 
     fn try_from(key: KeyboardKey) -> Result<Self, Self::Error> {
         Ok(match key {
             KeyboardKey::NULL => Self::None,
-            // KeyboardKey::ErrorRollover => Self::ErrorRollover,
-            // KeyboardKey::PostFail => Self::PostFail,
-            // KeyboardKey::ErrorUndefined => Self::ErrorUndefined,
             KeyboardKey::A => Self::A,
             KeyboardKey::B => Self::B,
             KeyboardKey::C => Self::C,
@@ -264,65 +262,63 @@ impl TryFrom<KeyboardKey> for KMBoxKeyboardKeyCode {
             KeyboardKey::X => Self::X,
             KeyboardKey::Y => Self::Y,
             KeyboardKey::Z => Self::Z,
-            // KeyboardKey::OneExclamationMark => Self::OneExclamationMark,
-            // KeyboardKey::TwoAt => Self::TwoAt,
-            // KeyboardKey::ThreeNumberSign => Self::ThreeNumberSign,
-            // KeyboardKey::FourDollar => Self::FourDollar,
-            // KeyboardKey::FivePercent => Self::FivePercent,
-            // KeyboardKey::SixCaret => Self::SixCaret,
-            // KeyboardKey::SevenAmpersand => Self::SevenAmpersand,
-            // KeyboardKey::EightAsterisk => Self::EightAsterisk,
-            // KeyboardKey::NineOParenthesis => Self::NineOParenthesis,
-            // KeyboardKey::ZeroCParenthesis => Self::ZeroCParenthesis,
-            // KeyboardKey::Enter => Self::Enter,
-            // KeyboardKey::Escape => Self::Escape,
-            // KeyboardKey::Backspace => Self::Backspace,
-            // KeyboardKey::Tab => Self::Tab,
-            // KeyboardKey::Spacebar => Self::Spacebar,
-            // KeyboardKey::MinusUnderscore => Self::MinusUnderscore,
-            // KeyboardKey::EqualPlus => Self::EqualPlus,
-            // KeyboardKey::OBracketAndOBrace => Self::OBracketAndOBrace,
-            // KeyboardKey::CBracketAndCBrace => Self::CBracketAndCBrace,
-            // KeyboardKey::BackslashVerticalBar => Self::BackslashVerticalBar,
-            // KeyboardKey::NonUSNumberSignTilde => Self::NonUSNumberSignTilde,
-            // KeyboardKey::SemicolonColon => Self::SemicolonColon,
-            // KeyboardKey::SingleAndDoubleQuote => Self::SingleAndDoubleQuote,
-            // KeyboardKey::GraveAccentAndTilde => Self::GraveAccentAndTilde,
-            // KeyboardKey::CommaAndLess => Self::CommaAndLess,
-            // KeyboardKey::DotGreater => Self::DotGreater,
-            // KeyboardKey::SlashQuestion => Self::SlashQuestion,
-            // KeyboardKey::CapsLock => Self::CapsLock,
-            // KeyboardKey::F1 => Self::F1,
-            // KeyboardKey::F2 => Self::F2,
-            // KeyboardKey::F3 => Self::F3,
-            // KeyboardKey::F4 => Self::F4,
-            // KeyboardKey::F5 => Self::F5,
-            // KeyboardKey::F6 => Self::F6,
-            // KeyboardKey::F7 => Self::F7,
-            // KeyboardKey::F8 => Self::F8,
-            // KeyboardKey::F9 => Self::F9,
-            // KeyboardKey::F10 => Self::F10,
-            // KeyboardKey::F11 => Self::F11,
-            // KeyboardKey::F12 => Self::F12,
-            // KeyboardKey::PrintScreen => Self::PrintScreen,
-            // KeyboardKey::ScrollLock => Self::ScrollLock,
-            // KeyboardKey::Pause => Self::Pause,
-            // KeyboardKey::Insert => Self::Insert,
-            // KeyboardKey::Home => Self::Home,
-            // KeyboardKey::PageUp => Self::PageUp,
-            // KeyboardKey::Delete => Self::Delete,
-            // KeyboardKey::End1 => Self::End1,
-            // KeyboardKey::PageDown => Self::PageDown,
-            // KeyboardKey::RightArrow => Self::RightArrow,
-            // KeyboardKey::LeftArrow => Self::LeftArrow,
-            // KeyboardKey::DownArrow => Self::DownArrow,
-            // KeyboardKey::UpArrow => Self::UpArrow,
-            // KeyboardKey::KeypadNumLockAndClear => Self::KeypadNumLockAndClear,
-            // KeyboardKey::KeypadSlash => Self::KeypadSlash,
-            // KeyboardKey::KeypadAsterisk => Self::KeypadAsterisk,
-            // KeyboardKey::KeypadMinus => Self::KeypadMinus,
-            // KeyboardKey::KeypadPlus => Self::KeypadPlus,
-            // KeyboardKey::KeypadEnter => Self::KeypadEnter,
+            KeyboardKey::KEY_1 => Self::OneExclamationMark,
+            KeyboardKey::KEY_2 => Self::TwoAt,
+            KeyboardKey::KEY_3 => Self::ThreeNumberSign,
+            KeyboardKey::KEY_4 => Self::FourDollar,
+            KeyboardKey::KEY_5 => Self::FivePercent,
+            KeyboardKey::KEY_6 => Self::SixCaret,
+            KeyboardKey::KEY_7 => Self::SevenAmpersand,
+            KeyboardKey::KEY_8 => Self::EightAsterisk,
+            KeyboardKey::KEY_9 => Self::NineOParenthesis,
+            KeyboardKey::KEY_0 => Self::ZeroCParenthesis,
+            KeyboardKey::RETURN => Self::Enter,
+            KeyboardKey::ESCAPE => Self::Escape,
+            KeyboardKey::BACK => Self::Backspace,
+            KeyboardKey::TAB => Self::Tab,
+            KeyboardKey::SPACE => Self::Spacebar,
+            KeyboardKey::OEM_MINUS => Self::MinusUnderscore,
+            KeyboardKey::OEM_PLUS => Self::EqualPlus,
+            KeyboardKey::OEM_4 => Self::OBracketAndOBrace,
+            KeyboardKey::OEM_6 => Self::CBracketAndCBrace,
+            KeyboardKey::OEM_5 => Self::BackslashVerticalBar,
+            KeyboardKey::OEM_1 => Self::SemicolonColon,
+            KeyboardKey::OEM_7 => Self::SingleAndDoubleQuote,
+            KeyboardKey::OEM_3 => Self::GraveAccentAndTilde,
+            KeyboardKey::OEM_COMMA => Self::CommaAndLess,
+            KeyboardKey::OEM_PERIOD => Self::DotGreater,
+            KeyboardKey::OEM_2 => Self::SlashQuestion,
+            KeyboardKey::CAPITAL => Self::CapsLock,
+            KeyboardKey::F1 => Self::F1,
+            KeyboardKey::F2 => Self::F2,
+            KeyboardKey::F3 => Self::F3,
+            KeyboardKey::F4 => Self::F4,
+            KeyboardKey::F5 => Self::F5,
+            KeyboardKey::F6 => Self::F6,
+            KeyboardKey::F7 => Self::F7,
+            KeyboardKey::F8 => Self::F8,
+            KeyboardKey::F9 => Self::F9,
+            KeyboardKey::F10 => Self::F10,
+            KeyboardKey::F11 => Self::F11,
+            KeyboardKey::F12 => Self::F12,
+            KeyboardKey::SNAPSHOT => Self::PrintScreen,
+            KeyboardKey::SCROLL => Self::ScrollLock,
+            KeyboardKey::PAUSE => Self::Pause,
+            KeyboardKey::INSERT => Self::Insert,
+            KeyboardKey::HOME => Self::Home,
+            KeyboardKey::PRIOR => Self::PageUp,
+            KeyboardKey::DELETE => Self::Delete,
+            KeyboardKey::END => Self::End1,
+            KeyboardKey::NEXT => Self::PageDown,
+            KeyboardKey::RIGHT => Self::RightArrow,
+            KeyboardKey::LEFT => Self::LeftArrow,
+            KeyboardKey::DOWN => Self::DownArrow,
+            KeyboardKey::UP => Self::UpArrow,
+            KeyboardKey::NUMLOCK => Self::KeypadNumLockAndClear,
+            KeyboardKey::DIVIDE => Self::KeypadSlash,
+            KeyboardKey::MULTIPLY => Self::KeypadAsterisk,
+            KeyboardKey::SUBTRACT => Self::KeypadMinus,
+            KeyboardKey::ADD => Self::KeypadPlus,
             KeyboardKey::NUMPAD1 => Self::Keypad1End,
             KeyboardKey::NUMPAD2 => Self::Keypad2DownArrow,
             KeyboardKey::NUMPAD3 => Self::Keypad3PageDown,
@@ -333,140 +329,321 @@ impl TryFrom<KeyboardKey> for KMBoxKeyboardKeyCode {
             KeyboardKey::NUMPAD8 => Self::Keypad8UpArrow,
             KeyboardKey::NUMPAD9 => Self::Keypad9PageUp,
             KeyboardKey::NUMPAD0 => Self::Keypad0Insert,
-            // KeyboardKey::KeypadDecimalSeparatorDelete => Self::KeypadDecimalSeparatorDelete,
-            // KeyboardKey::NonUSBackslashVerticalBar => Self::NonUSBackslashVerticalBar,
-            // KeyboardKey::Application => Self::Application,
-            // KeyboardKey::Power => Self::Power,
-            // KeyboardKey::KeypadEqual => Self::KeypadEqual,
-            // KeyboardKey::F13 => Self::F13,
-            // KeyboardKey::F14 => Self::F14,
-            // KeyboardKey::F15 => Self::F15,
-            // KeyboardKey::F16 => Self::F16,
-            // KeyboardKey::F17 => Self::F17,
-            // KeyboardKey::F18 => Self::F18,
-            // KeyboardKey::F19 => Self::F19,
-            // KeyboardKey::F20 => Self::F20,
-            // KeyboardKey::F21 => Self::F21,
-            // KeyboardKey::F22 => Self::F22,
-            // KeyboardKey::F23 => Self::F23,
-            // KeyboardKey::F24 => Self::F24,
-            // KeyboardKey::Execute => Self::Execute,
-            // KeyboardKey::Help => Self::Help,
-            // KeyboardKey::Menu => Self::Menu,
-            // KeyboardKey::Select => Self::Select,
-            // KeyboardKey::Stop => Self::Stop,
-            // KeyboardKey::Again => Self::Again,
-            // KeyboardKey::Undo => Self::Undo,
-            // KeyboardKey::Cut => Self::Cut,
-            // KeyboardKey::Copy => Self::Copy,
-            // KeyboardKey::Paste => Self::Paste,
-            // KeyboardKey::Find => Self::Find,
-            // KeyboardKey::Mute => Self::Mute,
-            // KeyboardKey::VolumeUp => Self::VolumeUp,
-            // KeyboardKey::VolumeDown => Self::VolumeDown,
-            // KeyboardKey::LockingCapsLock => Self::LockingCapsLock,
-            // KeyboardKey::LockingNumLock => Self::LockingNumLock,
-            // KeyboardKey::LockingScrollLock => Self::LockingScrollLock,
-            // KeyboardKey::KeypadComma => Self::KeypadComma,
-            // KeyboardKey::KeypadEqualSign => Self::KeypadEqualSign,
-            // KeyboardKey::International1 => Self::International1,
-            // KeyboardKey::International2 => Self::International2,
-            // KeyboardKey::International3 => Self::International3,
-            // KeyboardKey::International4 => Self::International4,
-            // KeyboardKey::International5 => Self::International5,
-            // KeyboardKey::International6 => Self::International6,
-            // KeyboardKey::International7 => Self::International7,
-            // KeyboardKey::International8 => Self::International8,
-            // KeyboardKey::International9 => Self::International9,
-            // KeyboardKey::Lang1 => Self::Lang1,
-            // KeyboardKey::Lang2 => Self::Lang2,
-            // KeyboardKey::Lang3 => Self::Lang3,
-            // KeyboardKey::Lang4 => Self::Lang4,
-            // KeyboardKey::Lang5 => Self::Lang5,
-            // KeyboardKey::Lang6 => Self::Lang6,
-            // KeyboardKey::Lang7 => Self::Lang7,
-            // KeyboardKey::Lang8 => Self::Lang8,
-            // KeyboardKey::Lang9 => Self::Lang9,
-            // KeyboardKey::AlternateErase => Self::AlternateErase,
-            // KeyboardKey::SysReq => Self::SysReq,
-            // KeyboardKey::Cancel => Self::Cancel,
-            // KeyboardKey::Clear => Self::Clear,
-            // KeyboardKey::Prior => Self::Prior,
-            // KeyboardKey::Return => Self::Return,
-            // KeyboardKey::Separator => Self::Separator,
-            // KeyboardKey::Out => Self::Out,
-            // KeyboardKey::Oper => Self::Oper,
-            // KeyboardKey::ClearAgain => Self::ClearAgain,
-            // KeyboardKey::CrSel => Self::CrSel,
-            // KeyboardKey::ExSel => Self::ExSel,
-            // KeyboardKey::Keypad00 => Self::Keypad00,
-            // KeyboardKey::Keypad000 => Self::Keypad000,
-            // KeyboardKey::ThousandsSeparator => Self::ThousandsSeparator,
-            // KeyboardKey::DecimalSeparator => Self::DecimalSeparator,
-            // KeyboardKey::CurrencyUnit => Self::CurrencyUnit,
-            // KeyboardKey::CurrencySubUnit => Self::CurrencySubUnit,
-            // KeyboardKey::KeypadOParenthesis => Self::KeypadOParenthesis,
-            // KeyboardKey::KeypadCParenthesis => Self::KeypadCParenthesis,
-            // KeyboardKey::KeypadOBrace => Self::KeypadOBrace,
-            // KeyboardKey::KeypadCBrace => Self::KeypadCBrace,
-            // KeyboardKey::KeypadTab => Self::KeypadTab,
-            // KeyboardKey::KeypadBackspace => Self::KeypadBackspace,
-            // KeyboardKey::KeypadA => Self::KeypadA,
-            // KeyboardKey::KeypadB => Self::KeypadB,
-            // KeyboardKey::KeypadC => Self::KeypadC,
-            // KeyboardKey::KeypadD => Self::KeypadD,
-            // KeyboardKey::KeypadE => Self::KeypadE,
-            // KeyboardKey::KeypadF => Self::KeypadF,
-            // KeyboardKey::KeypadXor => Self::KeypadXor,
-            // KeyboardKey::KeypadCaret => Self::KeypadCaret,
-            // KeyboardKey::KeypadPercent => Self::KeypadPercent,
-            // KeyboardKey::KeypadLess => Self::KeypadLess,
-            // KeyboardKey::KeypadGreater => Self::KeypadGreater,
-            // KeyboardKey::KeypadAmpersand => Self::KeypadAmpersand,
-            // KeyboardKey::KeypadLogicalAnd => Self::KeypadLogicalAnd,
-            // KeyboardKey::KeypadVerticalBar => Self::KeypadVerticalBar,
-            // KeyboardKey::KeypadLogicalOr => Self::KeypadLogicalOr,
-            // KeyboardKey::KeypadColon => Self::KeypadColon,
-            // KeyboardKey::KeypadNumberSign => Self::KeypadNumberSign,
-            // KeyboardKey::KeypadSpace => Self::KeypadSpace,
-            // KeyboardKey::KeypadAt => Self::KeypadAt,
-            // KeyboardKey::KeypadExclamationMark => Self::KeypadExclamationMark,
-            // KeyboardKey::KeypadMemoryStore => Self::KeypadMemoryStore,
-            // KeyboardKey::KeypadMemoryRecall => Self::KeypadMemoryRecall,
-            // KeyboardKey::KeypadMemoryClear => Self::KeypadMemoryClear,
-            // KeyboardKey::KeypadMemoryAdd => Self::KeypadMemoryAdd,
-            // KeyboardKey::KeypadMemorySubtract => Self::KeypadMemorySubtract,
-            // KeyboardKey::KeypadMemoryMultiply => Self::KeypadMemoryMultiply,
-            // KeyboardKey::KeypadMemoryDivide => Self::KeypadMemoryDivide,
-            // KeyboardKey::KeypadPlusMinus => Self::KeypadPlusMinus,
-            // KeyboardKey::KeypadClear => Self::KeypadClear,
-            // KeyboardKey::KeypadClearEntry => Self::KeypadClearEntry,
-            // KeyboardKey::KeypadBinary => Self::KeypadBinary,
-            // KeyboardKey::KeypadOctal => Self::KeypadOctal,
-            // KeyboardKey::KeypadDecimal => Self::KeypadDecimal,
-            // KeyboardKey::KeypadHexadecimal => Self::KeypadHexadecimal,
-            // KeyboardKey::LeftControl => Self::LeftControl,
-            // KeyboardKey::LeftShift => Self::LeftShift,
-            // KeyboardKey::LeftAlt => Self::LeftAlt,
+            KeyboardKey::DECIMAL => Self::KeypadDecimalSeparatorDelete,
+            KeyboardKey::OEM_102 => Self::NonUSBackslashVerticalBar,
+            KeyboardKey::APPS => Self::Application,
+            KeyboardKey::F13 => Self::F13,
+            KeyboardKey::F14 => Self::F14,
+            KeyboardKey::F15 => Self::F15,
+            KeyboardKey::F16 => Self::F16,
+            KeyboardKey::F17 => Self::F17,
+            KeyboardKey::F18 => Self::F18,
+            KeyboardKey::F19 => Self::F19,
+            KeyboardKey::F20 => Self::F20,
+            KeyboardKey::F21 => Self::F21,
+            KeyboardKey::F22 => Self::F22,
+            KeyboardKey::F23 => Self::F23,
+            KeyboardKey::F24 => Self::F24,
+            KeyboardKey::EXECUTE => Self::Execute,
+            KeyboardKey::HELP => Self::Help,
+            KeyboardKey::SELECT => Self::Select,
+            KeyboardKey::BROWSER_STOP => Self::Stop,
+            KeyboardKey::VOLUME_MUTE => Self::Mute,
+            KeyboardKey::VOLUME_UP => Self::VolumeUp,
+            KeyboardKey::VOLUME_DOWN => Self::VolumeDown,
+            KeyboardKey::SEPARATOR => Self::KeypadComma,
+            KeyboardKey::KANA => Self::Lang1,
+            KeyboardKey::HANJA => Self::Lang2,
+            KeyboardKey::CONVERT => Self::International4,
+            KeyboardKey::NONCONVERT => Self::International5,
+            KeyboardKey::CANCEL => Self::Cancel,
+            KeyboardKey::CLEAR => Self::Clear,
+            KeyboardKey::CRSEL => Self::CrSel,
+            KeyboardKey::EXSEL => Self::ExSel,
+            KeyboardKey::LCONTROL => Self::LeftControl,
+            KeyboardKey::LSHIFT => Self::LeftShift,
+            KeyboardKey::LMENU => Self::LeftAlt,
             KeyboardKey::LWIN => Self::LeftGui,
-            // KeyboardKey::RightControl => Self::RightControl,
-            // KeyboardKey::RightShift => Self::RightShift,
-            // KeyboardKey::RightAlt => Self::RightAlt,
+            KeyboardKey::RCONTROL => Self::RightControl,
+            KeyboardKey::RSHIFT => Self::RightShift,
+            KeyboardKey::RMENU => Self::RightAlt,
             KeyboardKey::RWIN => Self::RightGui,
+            // the side independent modifiers press the left key
+            KeyboardKey::SHIFT => Self::LeftShift,
+            KeyboardKey::CONTROL => Self::LeftControl,
+            KeyboardKey::MENU => Self::LeftAlt,
+            _ => return Err(InputFlowError::InvalidKey),
+        })
+    }
+}
+
+/// Maps a HID keycode back to the inputflow key, the inverse of `TryFrom<KeyboardKey>`.
+/// Modifiers map to the side specific keys (`LSHIFT` rather than `SHIFT`).
+impl TryFrom<KMBoxKeyboardKeyCode> for KeyboardKey {
+    type Error = InputFlowError;
+
+    fn try_from(key: KMBoxKeyboardKeyCode) -> Result<Self, Self::Error> {
+        Ok(match key {
+            KMBoxKeyboardKeyCode::None => Self::NULL,
+            KMBoxKeyboardKeyCode::A => Self::A,
+            KMBoxKeyboardKeyCode::B => Self::B,
+            KMBoxKeyboardKeyCode::C => Self::C,
+            KMBoxKeyboardKeyCode::D => Self::D,
+            KMBoxKeyboardKeyCode::E => Self::E,
+            KMBoxKeyboardKeyCode::F => Self::F,
+            KMBoxKeyboardKeyCode::G => Self::G,
+            KMBoxKeyboardKeyCode::H => Self::H,
+            KMBoxKeyboardKeyCode::I => Self::I,
+            KMBoxKeyboardKeyCode::J => Self::J,
+            KMBoxKeyboardKeyCode::K => Self::K,
+            KMBoxKeyboardKeyCode::L => Self::L,
+            KMBoxKeyboardKeyCode::M => Self::M,
+            KMBoxKeyboardKeyCode::N => Self::N,
+            KMBoxKeyboardKeyCode::O => Self::O,
+            KMBoxKeyboardKeyCode::P => Self::P,
+            KMBoxKeyboardKeyCode::Q => Self::Q,
+            KMBoxKeyboardKeyCode::R => Self::R,
+            KMBoxKeyboardKeyCode::S => Self::S,
+            KMBoxKeyboardKeyCode::T => Self::T,
+            KMBoxKeyboardKeyCode::U => Self::U,
+            KMBoxKeyboardKeyCode::V => Self::V,
+            KMBoxKeyboardKeyCode::W => Self::W,
+            KMBoxKeyboardKeyCode::X => Self::X,
+            KMBoxKeyboardKeyCode::Y => Self::Y,
+            KMBoxKeyboardKeyCode::Z => Self::Z,
+            KMBoxKeyboardKeyCode::OneExclamationMark => Self::KEY_1,
+            KMBoxKeyboardKeyCode::TwoAt => Self::KEY_2,
+            KMBoxKeyboardKeyCode::ThreeNumberSign => Self::KEY_3,
+            KMBoxKeyboardKeyCode::FourDollar => Self::KEY_4,
+            KMBoxKeyboardKeyCode::FivePercent => Self::KEY_5,
+            KMBoxKeyboardKeyCode::SixCaret => Self::KEY_6,
+            KMBoxKeyboardKeyCode::SevenAmpersand => Self::KEY_7,
+            KMBoxKeyboardKeyCode::EightAsterisk => Self::KEY_8,
+            KMBoxKeyboardKeyCode::NineOParenthesis => Self::KEY_9,
+            KMBoxKeyboardKeyCode::ZeroCParenthesis => Self::KEY_0,
+            KMBoxKeyboardKeyCode::Enter => Self::RETURN,
+            KMBoxKeyboardKeyCode::Escape => Self::ESCAPE,
+            KMBoxKeyboardKeyCode::Backspace => Self::BACK,
+            KMBoxKeyboardKeyCode::Tab => Self::TAB,
+            KMBoxKeyboardKeyCode::Spacebar => Self::SPACE,
+            KMBoxKeyboardKeyCode::MinusUnderscore => Self::OEM_MINUS,
+            KMBoxKeyboardKeyCode::EqualPlus => Self::OEM_PLUS,
+            KMBoxKeyboardKeyCode::OBracketAndOBrace => Self::OEM_4,
+            KMBoxKeyboardKeyCode::CBracketAndCBrace => Self::OEM_6,
+            KMBoxKeyboardKeyCode::BackslashVerticalBar => Self::OEM_5,
+            KMBoxKeyboardKeyCode::SemicolonColon => Self::OEM_1,
+            KMBoxKeyboardKeyCode::SingleAndDoubleQuote => Self::OEM_7,
+            KMBoxKeyboardKeyCode::GraveAccentAndTilde => Self::OEM_3,
+            KMBoxKeyboardKeyCode::CommaAndLess => Self::OEM_COMMA,
+            KMBoxKeyboardKeyCode::DotGreater => Self::OEM_PERIOD,
+            KMBoxKeyboardKeyCode::SlashQuestion => Self::OEM_2,
+            KMBoxKeyboardKeyCode::CapsLock => Self::CAPITAL,
+            KMBoxKeyboardKeyCode::F1 => Self::F1,
+            KMBoxKeyboardKeyCode::F2 => Self::F2,
+            KMBoxKeyboardKeyCode::F3 => Self::F3,
+            KMBoxKeyboardKeyCode::F4 => Self::F4,
+            KMBoxKeyboardKeyCode::F5 => Self::F5,
+            KMBoxKeyboardKeyCode::F6 => Self::F6,
+            KMBoxKeyboardKeyCode::F7 => Self::F7,
+            KMBoxKeyboardKeyCode::F8 => Self::F8,
+            KMBoxKeyboardKeyCode::F9 => Self::F9,
+            KMBoxKeyboardKeyCode::F10 => Self::F10,
+            KMBoxKeyboardKeyCode::F11 => Self::F11,
+            KMBoxKeyboardKeyCode::F12 => Self::F12,
+            KMBoxKeyboardKeyCode::PrintScreen => Self::SNAPSHOT,
+            KMBoxKeyboardKeyCode::ScrollLock => Self::SCROLL,
+            KMBoxKeyboardKeyCode::Pause => Self::PAUSE,
+            KMBoxKeyboardKeyCode::Insert => Self::INSERT,
+            KMBoxKeyboardKeyCode::Home => Self::HOME,
+            KMBoxKeyboardKeyCode::PageUp => Self::PRIOR,
+            KMBoxKeyboardKeyCode::Delete => Self::DELETE,
+            KMBoxKeyboardKeyCode::End1 => Self::END,
+            KMBoxKeyboardKeyCode::PageDown => Self::NEXT,
+            KMBoxKeyboardKeyCode::RightArrow => Self::RIGHT,
+            KMBoxKeyboardKeyCode::LeftArrow => Self::LEFT,
+            KMBoxKeyboardKeyCode::DownArrow => Self::DOWN,
+            KMBoxKeyboardKeyCode::UpArrow => Self::UP,
+            KMBoxKeyboardKeyCode::KeypadNumLockAndClear => Self::NUMLOCK,
+            KMBoxKeyboardKeyCode::KeypadSlash => Self::DIVIDE,
+            KMBoxKeyboardKeyCode::KeypadAsterisk => Self::MULTIPLY,
+            KMBoxKeyboardKeyCode::KeypadMinus => Self::SUBTRACT,
+            KMBoxKeyboardKeyCode::KeypadPlus => Self::ADD,
+            KMBoxKeyboardKeyCode::Keypad1End => Self::NUMPAD1,
+            KMBoxKeyboardKeyCode::Keypad2DownArrow => Self::NUMPAD2,
+            KMBoxKeyboardKeyCode::Keypad3PageDown => Self::NUMPAD3,
+            KMBoxKeyboardKeyCode::Keypad4LeftArrow => Self::NUMPAD4,
+            KMBoxKeyboardKeyCode::Keypad5 => Self::NUMPAD5,
+            KMBoxKeyboardKeyCode::Keypad6RightArrow => Self::NUMPAD6,
+            KMBoxKeyboardKeyCode::Keypad7Home => Self::NUMPAD7,
+            KMBoxKeyboardKeyCode::Keypad8UpArrow => Self::NUMPAD8,
+            KMBoxKeyboardKeyCode::Keypad9PageUp => Self::NUMPAD9,
+            KMBoxKeyboardKeyCode::Keypad0Insert => Self::NUMPAD0,
+            KMBoxKeyboardKeyCode::KeypadDecimalSeparatorDelete => Self::DECIMAL,
+            KMBoxKeyboardKeyCode::NonUSBackslashVerticalBar => Self::OEM_102,
+            KMBoxKeyboardKeyCode::Application => Self::APPS,
+            KMBoxKeyboardKeyCode::F13 => Self::F13,
+            KMBoxKeyboardKeyCode::F14 => Self::F14,
+            KMBoxKeyboardKeyCode::F15 => Self::F15,
+            KMBoxKeyboardKeyCode::F16 => Self::F16,
+            KMBoxKeyboardKeyCode::F17 => Self::F17,
+            KMBoxKeyboardKeyCode::F18 => Self::F18,
+            KMBoxKeyboardKeyCode::F19 => Self::F19,
+            KMBoxKeyboardKeyCode::F20 => Self::F20,
+            KMBoxKeyboardKeyCode::F21 => Self::F21,
+            KMBoxKeyboardKeyCode::F22 => Self::F22,
+            KMBoxKeyboardKeyCode::F23 => Self::F23,
+            KMBoxKeyboardKeyCode::F24 => Self::F24,
+            KMBoxKeyboardKeyCode::Execute => Self::EXECUTE,
+            KMBoxKeyboardKeyCode::Help => Self::HELP,
+            KMBoxKeyboardKeyCode::Select => Self::SELECT,
+            KMBoxKeyboardKeyCode::Stop => Self::BROWSER_STOP,
+            KMBoxKeyboardKeyCode::Mute => Self::VOLUME_MUTE,
+            KMBoxKeyboardKeyCode::VolumeUp => Self::VOLUME_UP,
+            KMBoxKeyboardKeyCode::VolumeDown => Self::VOLUME_DOWN,
+            KMBoxKeyboardKeyCode::KeypadComma => Self::SEPARATOR,
+            KMBoxKeyboardKeyCode::Lang1 => Self::KANA,
+            KMBoxKeyboardKeyCode::Lang2 => Self::HANJA,
+            KMBoxKeyboardKeyCode::International4 => Self::CONVERT,
+            KMBoxKeyboardKeyCode::International5 => Self::NONCONVERT,
+            KMBoxKeyboardKeyCode::Cancel => Self::CANCEL,
+            KMBoxKeyboardKeyCode::Clear => Self::CLEAR,
+            KMBoxKeyboardKeyCode::CrSel => Self::CRSEL,
+            KMBoxKeyboardKeyCode::ExSel => Self::EXSEL,
+            KMBoxKeyboardKeyCode::LeftControl => Self::LCONTROL,
+            KMBoxKeyboardKeyCode::LeftShift => Self::LSHIFT,
+            KMBoxKeyboardKeyCode::LeftAlt => Self::LMENU,
+            KMBoxKeyboardKeyCode::LeftGui => Self::LWIN,
+            KMBoxKeyboardKeyCode::RightControl => Self::RCONTROL,
+            KMBoxKeyboardKeyCode::RightShift => Self::RSHIFT,
+            KMBoxKeyboardKeyCode::RightAlt => Self::RMENU,
+            KMBoxKeyboardKeyCode::RightGui => Self::RWIN,
             _ => return Err(InputFlowError::InvalidKey),
         })
     }
 }
 
+impl TryFrom<u8> for KMBoxKeyboardKeyCode {
+    type Error = InputFlowError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            // safety: every value in these ranges is a declared discriminant of the repr(u8) enum
+            0x00..=0xA4 | 0xB0..=0xDD | 0xE0..=0xE7 => {
+                Ok(unsafe { std::mem::transmute::<u8, Self>(code) })
+            }
+            _ => Err(InputFlowError::InvalidKey),
+        }
+    }
+}
+
 impl DisplayBytes for KMBoxKeyboardKeyCode {
     fn display_bytes(&self, output: &mut dyn std::io::Write) -> std::io::Result<()> {
-        write_bytes!(output, b"{}", (self.clone() as u8))
+        write_bytes!(output, b"{}", (*self as u8))
     }
 }
 
 impl std::fmt::Display for KMBoxKeyboardKeyCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f,"{:?}", self)
-        // write!(f,"{}", (self.clone() as u8))
+        write!(f, "{:?}", self)
+        // write!(f,"{}", (*self as u8))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys that map to a HID code shared with a side specific key
+    const ALIASES: &[(KeyboardKey, KeyboardKey)] = &[
+        (KeyboardKey::SHIFT, KeyboardKey::LSHIFT),
+        (KeyboardKey::CONTROL, KeyboardKey::LCONTROL),
+        (KeyboardKey::MENU, KeyboardKey::LMENU),
+    ];
+
+    fn all_keys() -> impl Iterator<Item = KeyboardKey> {
+        (0..=u8::MAX).filter_map(|code| KeyboardKey::try_from(code).ok())
+    }
+
+    fn all_hid_codes() -> impl Iterator<Item = KMBoxKeyboardKeyCode> {
+        (0..=u8::MAX).filter_map(|code| KMBoxKeyboardKeyCode::try_from(code).ok())
+    }
+
+    #[test]
+    fn hid_code_conversion_preserves_value() {
+        for code in 0..=u8::MAX {
+            if let Ok(hid) = KMBoxKeyboardKeyCode::try_from(code) {
+                assert_eq!(hid as u8, code);
+            }
+        }
+        assert_eq!(all_hid_codes().count(), 0xA5 + 0x2E + 0x08);
+    }
+
+    #[test]
+    fn every_mapped_key_round_trips() {
+        for key in all_keys() {
+            let Ok(hid) = KMBoxKeyboardKeyCode::try_from(key) else {
+                continue;
+            };
+            let expected = ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == key)
+                .map_or(key, |(_, side)| *side);
+            assert_eq!(
+                KeyboardKey::try_from(hid),
+                Ok(expected),
+                "{key:?} -> {hid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_mapped_hid_code_round_trips() {
+        for hid in all_hid_codes() {
+            let Ok(key) = KeyboardKey::try_from(hid) else {
+                continue;
+            };
+            assert_eq!(
+                KMBoxKeyboardKeyCode::try_from(key),
+                Ok(hid),
+                "{hid:?} -> {key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn keyboard_page_is_covered() {
+        let mapped = |code: u8| {
+            let hid = KMBoxKeyboardKeyCode::try_from(code).unwrap();
+            KeyboardKey::try_from(hid).is_ok()
+        };
+
+        // letters, digits, enter through caps lock and the function keys.
+        // non-US `#` shares OEM_5 with backslash, which maps to the US key
+        assert!((0x04..=0x45).all(|code| code == 0x32 || mapped(code)));
+        // navigation and the keypad, keypad enter has no virtual key of its own
+        assert!((0x46..=0x63).all(|code| code == 0x58 || mapped(code)));
+        // F13 - F24
+        assert!((0x68..=0x73).all(mapped));
+        // modifiers
+        assert!((0xE0..=0xE7).all(mapped));
+    }
+
+    #[test]
+    fn keys_without_hid_usage_are_invalid() {
+        for key in [
+            KeyboardKey::BROWSER_BACK,
+            KeyboardKey::LAUNCH_MAIL,
+            KeyboardKey::MEDIA_PLAY_PAUSE,
+            KeyboardKey::_Reserved,
+        ] {
+            assert_eq!(
+                KMBoxKeyboardKeyCode::try_from(key),
+                Err(InputFlowError::InvalidKey)
+            );
+        }
+        assert_eq!(
+            KeyboardKey::try_from(KMBoxKeyboardKeyCode::ErrorRollover),
+            Err(InputFlowError::InvalidKey)
+        );
+    }
+}
@@ -1,130 +1,785 @@
 //! KMBox plugin for inputflow.
 //! Controls user input over serial interface to KMBox device.
 
-use ::std::time::Duration;
+use ::std::collections::HashSet;
+use ::std::time::{Duration, Instant};
 
-use dataview::PodMethods;
+use format_bytes::format_bytes;
 use inputflow::prelude::*;
 use keycodes::KMBoxKeyboardKeyCode;
-use serialport::{SerialPort, SerialPortType, UsbPortInfo};
-use format_bytes::format_bytes;
+use transport::Transport;
 
 mod args;
+pub mod discovery;
 pub mod keycodes;
+mod net;
+pub mod transport;
 
-struct KMBoxPluginRoot {
-    controller: InputFlowKMBox,
-}
+/// Prompt the KMBox prints once it has finished handling a command
+const KM_PROMPT: &[u8] = b">>> ";
 
-impl KMBoxPluginRoot {
-    pub fn new(args: args::Args) -> std::result::Result<Self, Box<dyn std::error::Error>> {
-        log::info!("Initializing KMBox plugin with config {}", ron::to_string(&args)?);
+/// Mouse buttons the KMBox can mask, in the order intercepted presses and releases are reported
+const KM_MASKABLE_BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::XButton1,
+    MouseButton::XButton2,
+];
 
-        let mut port_path = args.com_port;
+/// An empty line, the KMBox answers it with nothing but its prompt
+const KM_PROBE: &[u8] = b"\r\n";
 
-        if args.auto_select {
-            let ports = serialport::available_ports()?;
+/// Longest a single read waits while the plugin waits for a response, so a port without read
+/// timeout does not spin and the response timeout is still checked often enough
+const KM_READ_INTERVAL: Duration = Duration::from_millis(10);
 
-            for port in ports {
-                log::trace!("Found serial port {} : {:?}", port.port_name, port.port_type);
+/// Wait before the first reconnect attempt after a failed one, doubles with every failure
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+/// Longest wait between two reconnect attempts
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(5);
 
-                match port.port_type {
-                    SerialPortType::UsbPort(UsbPortInfo{product: Some(product_name),..}) => {
-                        if product_name.starts_with(&args.device_name) {
-                            log::info!("Automatically loaded port {} from device {}", port.port_name, product_name);
-                            port_path = port.port_name;
-                            break;
-                        }
-                    },
-                    _=> {}
-                }
-            }
-                
-        }
+impl InputFlowKMBox {
+    /// Connects to the KMBox Net or opens the serial port selected by `args`
+    pub(crate) fn new(args: args::Args) -> Result<Self> {
+        log::info!(
+            "Initializing KMBox plugin with config {}",
+            ron::to_string(&args).unwrap_or_default()
+        );
 
-        Ok(KMBoxPluginRoot {
-            controller: InputFlowKMBox {
-                port: serialport::new(port_path, args.baud_rate)
-                        .timeout(Duration::from_millis(args.timeout_ms))
-                        .open()?,
-            }
-        })
-    }
-}
+        let connection = match &args.net_ip {
+            None => Connection::Serial(open_transport(&args)?),
+            Some(net_ip) => Connection::Net(net::KMBoxNet::connect(
+                net_ip,
+                args.net_port,
+                &args.net_uuid,
+                Duration::from_millis(args.response_timeout_ms),
+                args.fire_and_forget_moves,
+            )?),
+        };
 
-impl<'a> PluginInner<'a> for KMBoxPluginRoot {
-    type BorrowedType = Fwd<&'a mut InputFlowKMBox>;
+        Ok(Self::with_connection(connection, args, true))
+    }
 
-    type OwnedType = InputFlowKMBox;
-    type OwnedTypeMut = InputFlowKMBox;
+    /// Connects to the device selected by `args` in the plugin argument syntax, like loading the plugin does
+    pub fn connect(args: &str) -> Result<Self> {
+        Self::new(ConnectorArgs::parse(args)?.parse_into(args::ARG_HELP)?)
+    }
 
-    fn borrow_features(&'a mut self) -> Self::BorrowedType {
-        self.controller.forward_mut()
+    /// Runs the `km.*` text protocol over a transport opened by the caller, such as a pty pair.
+    ///
+    /// `args` uses the plugin argument syntax, arguments selecting the device are ignored.
+    /// The transport cannot be reopened, so the controller stays disconnected once it fails.
+    pub fn with_transport(transport: impl Transport + 'static, args: &str) -> Result<Self> {
+        let args: args::Args = ConnectorArgs::parse(args)?.parse_into(args::ARG_HELP)?;
+        Ok(Self::with_connection(
+            Connection::Serial(Box::new(transport)),
+            args,
+            false,
+        ))
     }
 
-    fn into_features(self) -> Self::OwnedType {
-        self.controller
+    fn with_connection(connection: Connection, args: args::Args, reopenable: bool) -> Self {
+        InputFlowKMBox {
+            connection,
+            intercepted_buttons: HashSet::new(),
+            intercepted_keys: HashSet::new(),
+            physical_buttons: HashSet::new(),
+            physical_keys: HashSet::new(),
+            held_buttons: HashSet::new(),
+            held_keys: HashSet::new(),
+            response_timeout: Duration::from_millis(args.response_timeout_ms),
+            fire_and_forget_moves: args.fire_and_forget_moves,
+            unacked: false,
+            state: ConnectionState::Connected,
+            state_changes: Vec::new(),
+            next_reconnect: Instant::now(),
+            reconnect_backoff: RECONNECT_BACKOFF_MIN,
+            args,
+            reopenable,
+        }
     }
+}
 
-    fn mut_features(&'a mut self) -> &'a mut Self::OwnedTypeMut {
-        &mut self.controller
+/// Opens the TCP connection or serial port selected by `args`
+fn open_transport(args: &args::Args) -> Result<Box<dyn Transport>> {
+    if let Some(tcp_addr) = &args.tcp_addr {
+        let transport =
+            transport::TcpTransport::connect(tcp_addr, Duration::from_millis(args.timeout_ms))
+                .map_err(|e| {
+                    let message = format!("failed to connect to {tcp_addr}: {e}");
+                    log::error!("Failed to load KMBox device: {message}.");
+                    InputFlowError::Io.with_message(message)
+                })?;
+        return Ok(Box::new(transport));
     }
+
+    let port_path = if args.auto_select {
+        let filter = discovery::DeviceFilter::from_args(args);
+        let devices = discovery::discover_devices()?;
+        let device = filter.select(&devices)?;
+        log::info!("Automatically selected kmbox {device}");
+        device.port_name.clone()
+    } else {
+        args.com_port.clone()
+    };
+
+    let port = serialport::new(&port_path, args.baud_rate)
+        .timeout(Duration::from_millis(args.timeout_ms))
+        .open()
+        .map_err(|e| {
+            let message = match e.kind {
+                serialport::ErrorKind::NoDevice
+                | serialport::ErrorKind::Io(std::io::ErrorKind::NotFound) => {
+                    format!("serial port {port_path} not found")
+                }
+                _ => format!("failed to open serial port {port_path}: {e}"),
+            };
+            log::error!("Failed to load KMBox device: {message}.");
+            InputFlowError::Io.with_message(message)
+        })?;
+    Ok(Box::new(port))
+}
+
+/// How commands reach the device
+#[derive(Debug)]
+enum Connection {
+    /// `km.*` text commands over a serial port or another [`Transport`]
+    Serial(Box<dyn Transport>),
+    /// binary packets to a KMBox Net over udp
+    Net(net::KMBoxNet),
 }
 
 #[derive(Debug)]
 pub struct InputFlowKMBox {
-    pub port: Box<dyn SerialPort>,
+    connection: Connection,
+    /// masked physical buttons
+    intercepted_buttons: HashSet<MouseButton>,
+    /// masked physical keys
+    intercepted_keys: HashSet<KeyboardKey>,
+    /// intercepted buttons the physical mouse held at the last poll
+    physical_buttons: HashSet<MouseButton>,
+    /// intercepted keys the physical keyboard held at the last poll
+    physical_keys: HashSet<KeyboardKey>,
+    /// buttons this controller pressed on the target and has not released yet
+    held_buttons: HashSet<MouseButton>,
+    /// keys this controller pressed on the target and has not released yet
+    held_keys: HashSet<KeyboardKey>,
+    /// how long the device may stay quiet before a command counts as unanswered
+    response_timeout: Duration,
+    /// send mouse moves without waiting for the device to acknowledge them
+    fire_and_forget_moves: bool,
+    /// acknowledgements of fire and forget commands may still be waiting in the input buffer
+    unacked: bool,
+    state: ConnectionState,
+    /// state changes the host has not polled yet
+    state_changes: Vec<ConnectionState>,
+    /// earliest time of the next reconnect attempt while disconnected
+    next_reconnect: Instant,
+    reconnect_backoff: Duration,
+    /// arguments the device was selected with, discovery runs again on reconnect
+    args: args::Args,
+    /// false for transports handed in by the caller, those cannot be opened again
+    reopenable: bool,
 }
 
 impl InputFlowKMBox {
-
-    /// calls km.left() with value to set current left click
-    /// 1 = set down
-    /// 0 = release
-    pub fn km_set_left(&mut self, is_down: i32) -> Result<()> {
-        let cmd = format_bytes!(b"km.left({})\r\n", is_down);
-
-        self.port.write(cmd.as_bytes()).map_err(|e| {
-            // log serial failure details if logging is enabled
-            log::warn!("command km.left({is_down}) \"{cmd:?}\" failed: {e:?}.");
-            // return error to result as InputFlowError type.
-            InputFlowError::SendError
-        })?;
-        Ok(())
+    /// Presses (`is_down`) or releases a mouse button.
+    /// Scroll buttons turn one wheel notch on press, their release sends nothing.
+    pub fn km_set_button(&mut self, button: MouseButton, is_down: bool) -> Result<()> {
+        let event = if is_down {
+            InputEvent::ButtonDown(button)
+        } else {
+            InputEvent::ButtonUp(button)
+        };
+        self.km_send_event(event)
     }
 
     pub fn km_set_key(&mut self, key: KeyboardKey, is_down: bool) -> Result<()> {
-        let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
-
-        let cmd = if is_down {
-            format_bytes!(b"km.down({})\r\n", km_key)
+        let event = if is_down {
+            InputEvent::KeyDown(key)
         } else {
-            format_bytes!(b"km.up({})\r\n", km_key)
+            InputEvent::KeyUp(key)
         };
-
-        self.port.write(cmd.as_bytes()).map_err(|e| {
-            // log serial failure details if logging is enabled
-            log::warn!("command km.down/km.up \"{cmd:?}\" failed: {e:?}.");
-            // return error to result as InputFlowError type.
-            InputFlowError::SendError
-        })?;
-        Ok(())
+        self.km_send_event(event)
     }
 
     pub fn km_press_key(&mut self, key: KeyboardKey) -> Result<()> {
+        if let Connection::Net(_) = self.connection {
+            self.km_send_event(InputEvent::KeyDown(key))?;
+            return self.km_send_event(InputEvent::KeyUp(key));
+        }
+
         let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
-        
+
         // press key command with some timing variation
         let cmd = format_bytes!(b"km.press({},15,50)\r\n", km_key);
 
-        self.port.write(cmd.as_bytes()).map_err(|e| {
-            // log serial failure details if logging is enabled
-            log::warn!("command km.press({km_key}) \"{cmd:?}\" failed: {e:?}.");
-            // return error to result as InputFlowError type.
-            InputFlowError::SendError
+        self.km_send(&cmd)?;
+        // km.press ends with the key released
+        self.held_keys.remove(&key);
+        Ok(())
+    }
+
+    /// Sends a single input event and keeps track of what is held down on the target
+    fn km_send_event(&mut self, event: InputEvent) -> Result<()> {
+        self.km_forward_event(event)?;
+        self.track_held(event);
+        Ok(())
+    }
+
+    /// Sends a single input event without counting it as held by this controller,
+    /// for input that stands for the physical mouse and keyboard
+    fn km_forward_event(&mut self, event: InputEvent) -> Result<()> {
+        if let Connection::Net(net) = &mut self.connection {
+            return net.send_event(event);
+        }
+
+        let cmd = km_event_cmd(event)?;
+        if let InputEvent::MoveRelative { .. } = event {
+            self.km_send_move(&cmd)?;
+        } else if !cmd.is_empty() {
+            self.km_send(&cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Records the effect of an event that was sent successfully on the held keys and buttons
+    fn track_held(&mut self, event: InputEvent) {
+        match event {
+            // scroll buttons are a single wheel notch and never stay down
+            InputEvent::ButtonDown(button) if mouse_button_to_km_wheel(button).is_none() => {
+                self.held_buttons.insert(button);
+            }
+            InputEvent::ButtonUp(button) => {
+                self.held_buttons.remove(&button);
+            }
+            InputEvent::KeyDown(key) => {
+                self.held_keys.insert(key);
+            }
+            InputEvent::KeyUp(key) => {
+                self.held_keys.remove(&key);
+            }
+            _ => {}
+        }
+    }
+
+    /// Releases every button this controller is holding down on the target.
+    /// Buttons that fail to release stay tracked so a later call can retry them.
+    fn release_held_buttons(&mut self) -> Result<()> {
+        let held: Vec<_> = self.held_buttons.iter().copied().collect();
+        let stuck: Vec<_> = held
+            .into_iter()
+            .filter(|button| self.km_set_button(*button, false).is_err())
+            .map(|button| button.to_string())
+            .collect();
+        km_release_result(stuck)
+    }
+
+    /// Releases every key this controller is holding down on the target.
+    /// Keys that fail to release stay tracked so a later call can retry them.
+    fn release_held_keys(&mut self) -> Result<()> {
+        let held: Vec<_> = self.held_keys.iter().copied().collect();
+        let stuck: Vec<_> = held
+            .into_iter()
+            .filter(|key| self.km_set_key(*key, false).is_err())
+            .map(|key| format!("{key:?}"))
+            .collect();
+        km_release_result(stuck)
+    }
+}
+
+/// Turns the inputs that could not be released into an error listing them
+fn km_release_result(stuck: Vec<String>) -> Result<()> {
+    if stuck.is_empty() {
+        return Ok(());
+    }
+    Err(InputFlowError::SendError.with_message(format!("unable to release {}", stuck.join(", "))))
+}
+
+// release everything on drop so the target is never left with stuck input
+impl Drop for InputFlowKMBox {
+    fn drop(&mut self) {
+        let _ = catch_panic(|| {
+            let mut stuck = Vec::new();
+            if self.release_held_buttons().is_err() {
+                stuck.extend(take_last_error());
+            }
+            if self.release_held_keys().is_err() {
+                stuck.extend(take_last_error());
+            }
+            // masked physical input would stay blocked once nobody polls it anymore
+            if self.is_intercepting() && self.clear_intercepts().is_err() {
+                stuck.extend(take_last_error());
+            }
+            if !stuck.is_empty() {
+                log::warn!(
+                    "kmbox dropped with input still held down: {}.",
+                    stuck.join(", ")
+                );
+            }
+            Ok(())
+        });
+    }
+}
+
+impl InputFlowKMBox {
+    /// Runs `io` on the serial link, reconnecting if the device went away.
+    ///
+    /// While disconnected, calls fail right away until the backoff allows the next reconnect attempt.
+    /// If the link breaks during `io`, one reconnect is attempted immediately and `io` retried,
+    /// so a device that was replugged between two calls goes unnoticed by the host.
+    /// A timeout only counts as a broken link if the device does not answer a probe either,
+    /// a hung device or one unplugged behind a port that stays open never reports an error itself.
+    /// `io` is called again as a whole, callers that must not repeat what the old device already
+    /// did keep track of it themselves, see [`Self::km_send_within`].
+    fn km_link<T>(&mut self, mut io: impl FnMut(&mut Self) -> Result<T>) -> Result<T> {
+        // udp has no link that could break
+        if let Connection::Net(_) = self.connection {
+            return io(self);
+        }
+
+        if self.state == ConnectionState::Disconnected {
+            self.km_reconnect()?;
+            return io(self);
+        }
+
+        let result = io(self);
+        let lost = match result {
+            Err(InputFlowError::Disconnected | InputFlowError::Io) => true,
+            Err(InputFlowError::Timeout) => !self.km_probe(),
+            _ => false,
+        };
+        if !lost {
+            return result;
+        }
+
+        log::warn!(
+            "kmbox serial link lost: {}.",
+            take_last_error().unwrap_or_default()
+        );
+        self.set_state(ConnectionState::Disconnected);
+        self.km_reconnect()?;
+        io(self)
+    }
+
+    /// Whether the device still answers after a command timed out, the error of the command is kept
+    fn km_probe(&mut self) -> bool {
+        let error = take_last_error();
+        let answered = self
+            .km_send_within_once(KM_PROBE, self.response_timeout, &mut 0)
+            .is_ok();
+        if let (true, Some(error)) = (answered, error) {
+            set_last_error(error);
+        }
+        // the answer to the command may still arrive late, it is not mistaken for the next one
+        self.unacked = true;
+        answered
+    }
+
+    /// Runs discovery again, reopens the port and restores what this controller held and masked.
+    /// Fails with [`InputFlowError::Disconnected`] if the device is still gone or the backoff is not over.
+    fn km_reconnect(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if now < self.next_reconnect {
+            return Err(InputFlowError::Disconnected.with_message(format!(
+                "kmbox disconnected, next reconnect attempt in {:?}",
+                self.next_reconnect - now
+            )));
+        }
+
+        let reopened = if self.reopenable {
+            open_transport(&self.args)
+        } else {
+            Err(InputFlowError::Disconnected
+                .with_message("the transport was opened by the caller and cannot be reopened"))
+        };
+        let reconnected = reopened.and_then(|port| {
+            self.connection = Connection::Serial(port);
+            self.unacked = false;
+            self.km_restore_state()
+        });
+
+        match reconnected {
+            Ok(()) => {
+                log::info!("kmbox reconnected");
+                self.reconnect_backoff = RECONNECT_BACKOFF_MIN;
+                self.set_state(ConnectionState::Connected);
+                Ok(())
+            }
+            Err(_) => {
+                let reason = take_last_error().unwrap_or_default();
+                self.next_reconnect = now + self.reconnect_backoff;
+                self.reconnect_backoff = (self.reconnect_backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                log::warn!("kmbox reconnect failed: {reason}.");
+                Err(InputFlowError::Disconnected
+                    .with_message(format!("kmbox disconnected, reconnecting failed: {reason}")))
+            }
+        }
+    }
+
+    /// Presses the held keys and buttons and masks the intercepted inputs again on a fresh device.
+    /// With nothing to restore the device is probed, so a port that opens but has no device behind
+    /// it does not count as reconnected.
+    fn km_restore_state(&mut self) -> Result<()> {
+        let mut cmd = Vec::new();
+        for button in &self.held_buttons {
+            cmd.extend_from_slice(&km_button_cmd(*button, true)?);
+        }
+        for key in &self.held_keys {
+            cmd.extend_from_slice(&km_event_cmd(InputEvent::KeyDown(*key))?);
+        }
+        for button in &self.intercepted_buttons {
+            cmd.extend_from_slice(&km_mask_button_cmd(*button, true)?);
+        }
+        for key in &self.intercepted_keys {
+            cmd.extend_from_slice(&km_mask_key_cmd(*key, true)?);
+        }
+
+        // the fresh device passes nothing on, physical input still held is reported again
+        self.physical_buttons.clear();
+        self.physical_keys.clear();
+
+        if cmd.is_empty() {
+            cmd.extend_from_slice(KM_PROBE);
+        }
+        self.km_send_within_once(&cmd, self.response_timeout, &mut 0)
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        if self.state != state {
+            self.state = state;
+            self.state_changes.push(state);
+        }
+    }
+
+    /// The serial port for `km.*` text commands, KMBox Net units do not understand them
+    fn port(&mut self) -> Result<&mut Box<dyn Transport>> {
+        match &mut self.connection {
+            Connection::Serial(port) => Ok(port),
+            Connection::Net(_) => Err(InputFlowError::Unsupported
+                .with_message("not supported over the kmbox net connection")),
+        }
+    }
+
+    /// Sends a query command such as `km.left()` and returns the integer the device answers with.
+    /// The KMBox echoes the command back, prints the value on its own line and then the `>>> ` prompt.
+    pub fn km_query(&mut self, cmd: &[u8]) -> Result<i32> {
+        self.km_link(|s| s.km_query_once(cmd))
+    }
+
+    fn km_query_once(&mut self, cmd: &[u8]) -> Result<i32> {
+        // drop the echoes of previous commands so they are not mistaken for the answer
+        self.km_clear_input()?;
+
+        self.port()?
+            .write_all(cmd)
+            .map_err(|e| km_io_error(cmd, e))?;
+
+        let response = self.km_read_response(cmd, self.response_timeout, &mut 0)?;
+        let response = String::from_utf8_lossy(&response);
+
+        // the answer is the last line that is not the echo of our own command
+        response
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty() && !line.starts_with("km."))
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| {
+                log::warn!(
+                    "unexpected answer {response:?} to query {:?}.",
+                    String::from_utf8_lossy(cmd)
+                );
+                InputFlowError::SendError.with_message(format!(
+                    "unexpected kmbox answer {response:?} to {:?}",
+                    String::from_utf8_lossy(cmd).trim()
+                ))
+            })
+    }
+
+    /// Writes commands that do not answer with a value and waits for the device to acknowledge them
+    fn km_send(&mut self, cmd: &[u8]) -> Result<()> {
+        self.km_send_within(cmd, self.response_timeout)
+    }
+
+    /// [`Self::km_send`] for commands the device may take up to `timeout` to acknowledge.
+    /// If the link breaks halfway through a batch, the retry after the reconnect only repeats
+    /// what the old device did not acknowledge, see [`km_retry_cmd`].
+    fn km_send_within(&mut self, cmd: &[u8], timeout: Duration) -> Result<()> {
+        let mut cmd = cmd.to_vec();
+        self.km_link(|s| {
+            let mut acked = 0;
+            let result = s.km_send_within_once(&cmd, timeout, &mut acked);
+            if result.is_err() {
+                cmd = km_retry_cmd(&cmd, acked);
+            }
+            result
+        })
+    }
+
+    /// Sends `cmd` once, on failure `acked` is set to the length of the leading commands the
+    /// device acknowledged
+    fn km_send_within_once(
+        &mut self,
+        cmd: &[u8],
+        timeout: Duration,
+        acked: &mut usize,
+    ) -> Result<()> {
+        if self.unacked {
+            // the acknowledgements of fire and forget commands would be mistaken for ours
+            self.km_clear_input()?;
+        }
+
+        self.port()?
+            .write_all(cmd)
+            .map_err(|e| km_io_error(cmd, e))?;
+        self.km_read_response(cmd, timeout, acked)?;
+        Ok(())
+    }
+
+    /// Writes mouse moves, skipping the acknowledgement if fire and forget moves are enabled
+    fn km_send_move(&mut self, cmd: &[u8]) -> Result<()> {
+        self.km_send_move_over(cmd, Duration::ZERO)
+    }
+
+    /// [`Self::km_send_move`] for moves the device spreads out over `duration`,
+    /// it only acknowledges them once they are done
+    fn km_send_move_over(&mut self, cmd: &[u8], duration: Duration) -> Result<()> {
+        if !self.fire_and_forget_moves {
+            return self.km_send_within(cmd, self.response_timeout + duration);
+        }
+
+        self.km_link(|s| {
+            s.port()?.write_all(cmd).map_err(|e| km_io_error(cmd, e))?;
+            s.unacked = true;
+            Ok(())
+        })
+    }
+
+    /// Drops everything the device sent that was not read yet
+    fn km_clear_input(&mut self) -> Result<()> {
+        self.port()?.clear_input().map_err(|e| {
+            log::warn!("clearing serial input failed: {e:?}.");
+            InputFlowError::Io.with_message(format!("clearing serial input failed: {e}"))
         })?;
+        self.unacked = false;
         Ok(())
     }
+
+    /// Whether any physical input is masked
+    fn is_intercepting(&self) -> bool {
+        !self.intercepted_buttons.is_empty() || !self.intercepted_keys.is_empty()
+    }
+
+    /// Queries whether a mouse button is held on the physical mouse with `km.left()`, `km.right()`, ...
+    fn km_button_down(&mut self, button: MouseButton) -> Result<bool> {
+        let Some(km_name) = mouse_button_to_km_name(button) else {
+            return Err(InputFlowError::InvalidKey);
+        };
+        let cmd = format_bytes!(b"km.{}()\r\n", km_name);
+        Ok(self.km_query(&cmd)? != 0)
+    }
+
+    /// Queries whether a key is held on the physical keyboard with `km.isdown`
+    fn km_key_down(&mut self, key: KeyboardKey) -> Result<bool> {
+        let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
+        let cmd = format_bytes!(b"km.isdown({})\r\n", km_key);
+        Ok(self.km_query(&cmd)? != 0)
+    }
+
+    /// Queries every intercepted input and returns the presses and releases since the last poll,
+    /// buttons first and keys by their code
+    fn km_poll_physical(&mut self) -> Result<Vec<InputEvent>> {
+        let mut events = Vec::new();
+
+        for button in KM_MASKABLE_BUTTONS {
+            if !self.intercepted_buttons.contains(&button) {
+                continue;
+            }
+            let down = self.km_button_down(button)?;
+            if down && self.physical_buttons.insert(button) {
+                events.push(InputEvent::ButtonDown(button));
+            } else if !down && self.physical_buttons.remove(&button) {
+                events.push(InputEvent::ButtonUp(button));
+            }
+        }
+
+        let mut keys = self
+            .intercepted_keys
+            .iter()
+            .map(|key| Ok((KMBoxKeyboardKeyCode::try_from(*key)? as u8, *key)))
+            .collect::<Result<Vec<_>>>()?;
+        keys.sort_unstable_by_key(|(code, _)| *code);
+        for (_, key) in keys {
+            let down = self.km_key_down(key)?;
+            if down && self.physical_keys.insert(key) {
+                events.push(InputEvent::KeyDown(key));
+            } else if !down && self.physical_keys.remove(&key) {
+                events.push(InputEvent::KeyUp(key));
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Reads from the port until the KMBox echoed the last command of `cmd` and printed its prompt,
+    /// then returns everything before the prompt.
+    /// Fails if the device reports an error for any of the commands or stops answering for longer
+    /// than `timeout`, then `acked` is set to the length of the leading commands that were done.
+    fn km_read_response(
+        &mut self,
+        cmd: &[u8],
+        timeout: Duration,
+        acked: &mut usize,
+    ) -> Result<Vec<u8>> {
+        let port_timeout = Duration::from_millis(self.args.timeout_ms);
+        self.port()?
+            .set_read_timeout(timeout.clamp(Duration::from_millis(1), KM_READ_INTERVAL))
+            .map_err(|e| km_io_error(cmd, e))?;
+
+        let response = self.km_read_response_polling(cmd, timeout, acked);
+
+        self.port()?
+            .set_read_timeout(port_timeout)
+            .map_err(|e| km_io_error(cmd, e))?;
+        response
+    }
+
+    fn km_read_response_polling(
+        &mut self,
+        cmd: &[u8],
+        timeout: Duration,
+        acked: &mut usize,
+    ) -> Result<Vec<u8>> {
+        let echo = cmd
+            .split(|b| *b == b'\n')
+            .map(|line| line.trim_ascii())
+            .rfind(|line| !line.is_empty())
+            .unwrap_or_default();
+
+        let mut deadline = Instant::now() + timeout;
+        let mut response = Vec::new();
+        let mut buf = [0u8; 64];
+
+        while !(response.ends_with(KM_PROMPT) && contains(&response, echo)) {
+            if Instant::now() > deadline {
+                log::warn!(
+                    "timed out waiting for kmbox response to {:?}, got {:?} so far.",
+                    String::from_utf8_lossy(echo),
+                    String::from_utf8_lossy(&response)
+                );
+                let hint = if response.is_empty() {
+                    ", check the connection and baud rate"
+                } else {
+                    ""
+                };
+                *acked = km_acked_len(cmd, &response);
+                return Err(InputFlowError::Timeout.with_message(format!(
+                    "no kmbox response to {:?} within {:?}{hint}",
+                    String::from_utf8_lossy(echo),
+                    timeout
+                )));
+            }
+
+            match self.port()?.read(&mut buf) {
+                Ok(n) => {
+                    response.extend_from_slice(&buf[..n]);
+                    // the timeout applies to the device going quiet, long batches may take longer
+                    if n > 0 {
+                        deadline = Instant::now() + timeout;
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => {
+                    log::warn!("reading kmbox response failed: {e:?}.");
+                    *acked = km_acked_len(cmd, &response);
+                    return Err(InputFlowError::from(e));
+                }
+            }
+        }
+
+        response.truncate(response.len() - KM_PROMPT.len());
+
+        if let Some(error) = km_device_error(&String::from_utf8_lossy(&response)) {
+            log::warn!(
+                "kmbox rejected {:?}: {error}.",
+                String::from_utf8_lossy(cmd).trim()
+            );
+            return Err(InputFlowError::SendError.with_message(format!(
+                "kmbox rejected {:?}: {error}",
+                String::from_utf8_lossy(cmd).trim()
+            )));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Returns true if `needle` appears anywhere in `haystack`
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+/// Position of the first `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Length of the leading commands of `cmd` the device has finished according to `response`,
+/// a command is done once its echo is followed by a prompt
+fn km_acked_len(cmd: &[u8], response: &[u8]) -> usize {
+    let (mut acked, mut offset, mut read) = (0, 0, 0);
+    for line in cmd.split_inclusive(|b| *b == b'\n') {
+        offset += line.len();
+        let echo = line.trim_ascii();
+        if echo.is_empty() {
+            continue;
+        }
+        let Some(at) = find(&response[read..], echo) else {
+            break;
+        };
+        let after_echo = read + at + echo.len();
+        let Some(at) = find(&response[after_echo..], KM_PROMPT) else {
+            break;
+        };
+        read = after_echo + at + KM_PROMPT.len();
+        acked = offset;
+    }
+    acked
+}
+
+/// Commands that do something again every time they run, unlike pressing or releasing
+const KM_ONE_SHOT_CMDS: [&[u8]; 4] = [b"km.move(", b"km.wheel(", b"km.click(", b"km.press("];
+
+/// What to send to a reconnected device in place of `cmd`, of which the first `acked` bytes were
+/// done by the device that went away.
+/// The done moves, wheel turns, clicks and key presses are left out so they do not happen twice,
+/// the done presses and releases are sent again since the replugged device starts with nothing held.
+fn km_retry_cmd(cmd: &[u8], acked: usize) -> Vec<u8> {
+    let (done, pending) = cmd.split_at(acked);
+    let mut retry: Vec<u8> = done
+        .split_inclusive(|b| *b == b'\n')
+        .filter(|line| !KM_ONE_SHOT_CMDS.iter().any(|c| line.starts_with(c)))
+        .flatten()
+        .copied()
+        .collect();
+    retry.extend_from_slice(pending);
+    retry
+}
+
+/// Finds the exception the KMBox python interpreter printed for a failed command,
+/// such as `NameError: name 'km.foo' isn't defined`
+fn km_device_error(response: &str) -> Option<&str> {
+    response.lines().map(str::trim).find(|line| {
+        line.split_once(':').is_some_and(|(name, _)| {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && (name.ends_with("Error") || name.ends_with("Exception"))
+        })
+    })
 }
 
 impl Loadable for InputFlowKMBox {
@@ -140,142 +795,394 @@ impl Loadable for InputFlowKMBox {
 impl KeyboardWriter for InputFlowKMBox {
     #[doc = r"Sends keyboard press down event"]
     fn send_key_down(&mut self, key: KeyboardKey) -> Result<()> {
-        self.km_set_key(key, true)
+        catch_panic(|| self.km_set_key(key, true))
     }
 
     #[doc = r" Releases a key that was set to down previously"]
     fn send_key_up(&mut self, key: KeyboardKey) -> Result<()> {
-        self.km_set_key(key, false)
+        catch_panic(|| self.km_set_key(key, false))
     }
 
     #[doc = r" Presses a key and lets it go all in one for when users do not care about specific timings"]
     fn press_key(&mut self, key: KeyboardKey) -> Result<()> {
-        self.km_press_key(key)
+        catch_panic(|| self.km_press_key(key))
     }
 
     #[doc = r" clears all active pressed keys. Useful for cleaning up multiple keys presses in one go."]
     #[doc = r" Ensures that keyboard writer is set back into a neutral state."]
     fn clear_keys(&mut self) -> Result<()> {
-        // TODO: Add a currently pressed keys map and recursively set them unpressed
-        log::info!("kmbox clear_keys not implemented yet...");
-        Ok(())
+        catch_panic(|| self.release_held_keys())
+    }
+}
+
+impl KeyboardReader for InputFlowKMBox {
+    #[doc = r" Returns true if the key is currently held down on the physical keyboard, masked or not"]
+    fn is_key_down(&mut self, key: KeyboardKey) -> Result<bool> {
+        catch_panic(|| self.km_key_down(key))
     }
 }
 
 /// Takes in an inputflow mouse button and tries to
-/// convert it to the equivilent kmbox button id
-fn mouse_button_to_km(button: MouseButton) -> Option<u32> {
+/// convert it to the name of the matching kmbox button command (`km.left`, `km.right`, ...)
+fn mouse_button_to_km_name(button: MouseButton) -> Option<&'static [u8]> {
     Some(match button {
-        MouseButton::Left => 0,
-        MouseButton::Right => 1,
-        MouseButton::Middle => 3,
-        MouseButton::XButton1 => 4,
-        MouseButton::XButton2 => 5,
+        MouseButton::Left => b"left",
+        MouseButton::Right => b"right",
+        MouseButton::Middle => b"middle",
+        MouseButton::XButton1 => b"side1",
+        MouseButton::XButton2 => b"side2",
         _ => {
             return None;
         }
     })
 }
 
+/// Wheel notches a scroll button stands for, positive is up
+fn mouse_button_to_km_wheel(button: MouseButton) -> Option<i32> {
+    match button {
+        MouseButton::ScrollUp => Some(1),
+        MouseButton::ScrollDown => Some(-1),
+        _ => None,
+    }
+}
+
+/// Builds the command pressing or releasing a mouse button.
+/// Scroll buttons map to a single `km.wheel` notch on press, their release is empty.
+fn km_button_cmd(button: MouseButton, down: bool) -> Result<Vec<u8>> {
+    if let Some(notches) = mouse_button_to_km_wheel(button) {
+        return Ok(if down {
+            format_bytes!(b"km.wheel({})\r\n", notches)
+        } else {
+            Vec::new()
+        });
+    }
+
+    let Some(km_name) = mouse_button_to_km_name(button) else {
+        return Err(InputFlowError::InvalidKey);
+    };
+    Ok(format_bytes!(b"km.{}({})\r\n", km_name, i32::from(down)))
+}
+
 impl MouseWriter for InputFlowKMBox {
     #[doc = r" Sends mouse button press down event"]
     fn send_button_down(&mut self, button: MouseButton) -> Result<()> {
-        match button {
-            MouseButton::Left => {
-                self.km_set_left(1)
-            },
-            _=> {Err(InputFlowError::Parameter)}
-        }
+        catch_panic(|| self.km_set_button(button, true))
     }
 
     #[doc = r" Releases a mouse button that was set to down previously"]
     fn send_button_up(&mut self, button: MouseButton) -> Result<()> {
-        match button {
-            MouseButton::Left => {
-                self.km_set_left(0)
-            },
-            _=> {Err(InputFlowError::Parameter)}
-        }
+        catch_panic(|| self.km_set_button(button, false))
     }
 
     #[doc = r" Presses a  mouse button and lets it go all in one for when users do not care about specific timings"]
     fn click_button(&mut self, button: MouseButton) -> Result<()> {
+        catch_panic(|| {
+            if let Connection::Net(_) = self.connection {
+                self.km_send_event(InputEvent::ButtonDown(button))?;
+                return self.km_send_event(InputEvent::ButtonUp(button));
+            }
 
-        let Some(km_button) = mouse_button_to_km(button) else {
-            return Err(InputFlowError::InvalidKey);
-        };
-        
-        let cmd = match button {
-            MouseButton::Left => {
-                format_bytes!(b"km.click({})\r\n", km_button)
-            },
-            _=> {return Err(InputFlowError::Parameter);}
-        };
+            // press and release go out in a single write, scroll buttons only produce the press
+            let mut cmd = km_button_cmd(button, true)?;
+            cmd.extend_from_slice(&km_button_cmd(button, false)?);
 
-        // TODO: find anything other than km.click so that it may have some human-like delay rather than instantanious clicks
-        self.port.write(cmd.as_bytes()).map_err(|e| {
-            // log serial failure details if logging is enabled
-            log::warn!("command km.click({button:?}) \"{cmd:?}\" failed: {e:?}.");
-            // return error to result as InputFlowError type.
-            InputFlowError::SendError
-        })?;
-         
-        Ok(())
+            // TODO: add some human-like delay between press and release rather than instantanious clicks
+            self.km_send(&cmd)?;
+            self.track_held(InputEvent::ButtonUp(button));
+            Ok(())
+        })
     }
 
     #[doc = r" clears all active pressed  mouse buttons. Useful for cleaning up multiple mouse button presses in one go."]
     #[doc = r" Ensures that mouse writer is set back into a neutral state."]
     fn clear_buttons(&mut self) -> Result<()> {
-        Ok(())
+        catch_panic(|| self.release_held_buttons())
     }
 
     #[doc = r" Sends a mouse move command to move it x dpi-pixels horizontally, and y vertically"]
     fn mouse_move_relative(&mut self, x: i32, y: i32) -> Result<()> {
-        let cmd = format_bytes!(b"km.move({},{})\r\n", x,y);
-        self.port.write(cmd.as_bytes()).map_err(|e| {
-            // log serial failure details if logging is enabled
-            log::warn!("command km.move({x},{y}) \"{cmd:?}\" failed: {e:?}.");
-            // return error to result as InputFlowError type.
-            InputFlowError::SendError
-        })?;
-        Ok(())
+        catch_panic(|| self.km_send_event(InputEvent::MoveRelative { x, y }))
     }
 }
 
-// ================================================================================================================= 
-// =================================== CGlue Plugin init and Header definitions ====================================
-// ================================================================================================================= 
-
-cglue_impl_group!(InputFlowKMBox, ControllerFeatures,{KeyboardWriter, MouseWriter}, {KeyboardWriter, MouseWriter} );
-
-/// Exposed interface that is called by the user of the plugin to instantiate it
-#[allow(improper_ctypes_definitions)] // the linter is being stupid and not noticing the repr(u8)
-extern "C" fn create_plugin(lib: &CArc<cglue::trait_group::c_void>, args: *const std::ffi::c_char) -> Result<PluginInnerArcBox<'static>> {
-    env_logger::builder()
-    // .filter_level(log::LevelFilter::Info)
-    .init();
-    Ok(trait_obj!(
-        (
-            KMBoxPluginRoot::new(
-                args::parse_args(args).map_err(|e| {
-                    log::error!("Invalid parameters were passed to inputflow_kmbox: {e:?}.");
-                    InputFlowError::Parameter
-                })?
-            ).map_err(|e| {
-                log::error!("Failed to load KMBox device: {e:?}.");
-                InputFlowError::Loading
-            })?,
-            lib.clone()
-        ) as PluginInner
+impl SmoothMouseWriter for InputFlowKMBox {
+    #[doc = r" Moves the mouse by `x`, `y` in a straight line over `duration_ms` milliseconds"]
+    fn move_relative_over(&mut self, x: i32, y: i32, duration_ms: u32) -> Result<()> {
+        catch_panic(|| {
+            if let Connection::Net(net) = &mut self.connection {
+                return net.move_over(x, y, duration_ms, None);
+            }
+
+            let cmd = format_bytes!(b"km.move({},{},{})\r\n", x, y, duration_ms);
+            self.km_send_move_over(&cmd, Duration::from_millis(duration_ms.into()))
+        })
+    }
+
+    #[doc = r" Moves the mouse by `x`, `y` along a cubic bezier curve bent by `controls` over `duration_ms` milliseconds"]
+    fn move_bezier_over(
+        &mut self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        controls: BezierControls,
+    ) -> Result<()> {
+        catch_panic(|| {
+            if let Connection::Net(net) = &mut self.connection {
+                return net.move_over(x, y, duration_ms, Some(controls));
+            }
+
+            let BezierControls { x1, y1, x2, y2 } = controls;
+            let cmd = format_bytes!(
+                b"km.move({},{},{},{},{},{},{})\r\n",
+                x,
+                y,
+                duration_ms,
+                x1,
+                y1,
+                x2,
+                y2
+            );
+            self.km_send_move_over(&cmd, Duration::from_millis(duration_ms.into()))
+        })
+    }
+}
+
+/// Logs a failed serial write and converts it into the matching error, keeping the details as last error
+fn km_io_error(cmd: &[u8], e: std::io::Error) -> InputFlowError {
+    let cmd = String::from_utf8_lossy(cmd);
+    let cmd = cmd.trim();
+    // log serial failure details if logging is enabled
+    log::warn!("command {cmd:?} failed: {e:?}.");
+    let message = format!("sending {cmd:?} to kmbox failed: {e}");
+    InputFlowError::from(e).with_message(message)
+}
+
+/// Builds the `km.*` command that performs a single input event
+fn km_event_cmd(event: InputEvent) -> Result<Vec<u8>> {
+    Ok(match event {
+        InputEvent::KeyDown(key) => {
+            let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
+            format_bytes!(b"km.down({})\r\n", km_key)
+        }
+        InputEvent::KeyUp(key) => {
+            let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
+            format_bytes!(b"km.up({})\r\n", km_key)
+        }
+        InputEvent::ButtonDown(button) => km_button_cmd(button, true)?,
+        InputEvent::ButtonUp(button) => km_button_cmd(button, false)?,
+        InputEvent::MoveRelative { x, y } => format_bytes!(b"km.move({},{})\r\n", x, y),
+        InputEvent::Wheel(notches) => format_bytes!(b"km.wheel({})\r\n", notches),
+        InputEvent::MoveAbsolute { .. } => return Err(InputFlowError::Unsupported),
+    })
+}
+
+impl EventWriter for InputFlowKMBox {
+    #[doc = r" Sends all events in order as a single serial write, or one packet per event to a KMBox Net"]
+    fn send_events(&mut self, events: &[InputEvent]) -> Result<()> {
+        catch_panic(|| {
+            if let Connection::Net(_) = self.connection {
+                return events
+                    .iter()
+                    .try_for_each(|event| self.km_send_event(*event));
+            }
+
+            let mut cmd = Vec::new();
+            for event in events {
+                cmd.extend_from_slice(&km_event_cmd(*event)?);
+            }
+            if cmd.is_empty() {
+                return Ok(());
+            }
+
+            if events
+                .iter()
+                .all(|e| matches!(e, InputEvent::MoveRelative { .. }))
+            {
+                self.km_send_move(&cmd)?;
+            } else {
+                self.km_send(&cmd)?;
+            }
+            for event in events {
+                self.track_held(*event);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl MouseReader for InputFlowKMBox {
+    #[doc = r" Returns true if the mouse button is currently held down on the physical mouse, masked or not"]
+    fn is_button_down(&mut self, button: MouseButton) -> Result<bool> {
+        catch_panic(|| self.km_button_down(button))
+    }
+}
+
+/// Builds the command starting or stopping the interception of a physical mouse button
+fn km_mask_button_cmd(button: MouseButton, enabled: bool) -> Result<Vec<u8>> {
+    let Some(km_name) = mouse_button_to_km_name(button) else {
+        return Err(InputFlowError::InvalidKey);
+    };
+    Ok(format_bytes!(
+        b"km.mask_{}({})\r\n",
+        km_name,
+        i32::from(enabled)
     ))
 }
 
-/// Static plugin header values defining the plugin's capabilities
-#[no_mangle]
-pub static IF_PLUGIN_HEAD: PluginHeader = PluginHeader {
-    features: FeatureSupport::from_bits_retain(
-        FeatureSupport::WRITE_KEYBOARD.bits() | FeatureSupport::WRITE_MOUSE.bits(),
-    ),
-    layout: ROOT_LAYOUT,
-    create: create_plugin,
-};
+/// Builds the command starting or stopping the interception of a physical keyboard key
+fn km_mask_key_cmd(key: KeyboardKey, enabled: bool) -> Result<Vec<u8>> {
+    let km_key = KMBoxKeyboardKeyCode::try_from(key)?;
+    Ok(if enabled {
+        format_bytes!(b"km.mask_keyboard({})\r\n", km_key)
+    } else {
+        format_bytes!(b"km.unmask_keyboard({})\r\n", km_key)
+    })
+}
+
+impl InputInterceptor for InputFlowKMBox {
+    #[doc = r" Starts or stops intercepting a physical mouse button using `km.mask_*`"]
+    fn intercept_button(&mut self, button: MouseButton, enabled: bool) -> Result<()> {
+        catch_panic(|| {
+            self.km_send(&km_mask_button_cmd(button, enabled)?)?;
+
+            if enabled {
+                self.intercepted_buttons.insert(button);
+            } else {
+                self.intercepted_buttons.remove(&button);
+                self.physical_buttons.remove(&button);
+            }
+            Ok(())
+        })
+    }
+
+    #[doc = r" Starts or stops intercepting a physical keyboard key using `km.mask_keyboard`"]
+    fn intercept_key(&mut self, key: KeyboardKey, enabled: bool) -> Result<()> {
+        catch_panic(|| {
+            self.km_send(&km_mask_key_cmd(key, enabled)?)?;
+
+            if enabled {
+                self.intercepted_keys.insert(key);
+            } else {
+                self.intercepted_keys.remove(&key);
+                self.physical_keys.remove(&key);
+            }
+            Ok(())
+        })
+    }
+
+    #[doc = r" Stops all interception so physical input reaches the target unfiltered again."]
+    fn clear_intercepts(&mut self) -> Result<()> {
+        catch_panic(|| {
+            self.km_send(b"km.unmask_all()\r\n")?;
+            self.intercepted_buttons.clear();
+            self.intercepted_keys.clear();
+            self.physical_buttons.clear();
+            self.physical_keys.clear();
+            Ok(())
+        })
+    }
+
+    #[doc = r" Queries the physical state of every intercepted input with `km.left()`, `km.isdown()`, ..."]
+    #[doc = r" and calls `callback` for each press and release since the last poll, buttons first."]
+    #[doc = r" The KMBox does not report the input by itself, a press and release in between two polls is missed."]
+    fn poll_intercepted(&mut self, mut callback: InterceptCallback) -> Result<()> {
+        catch_panic(|| {
+            if !self.is_intercepting() {
+                return Ok(());
+            }
+
+            // masked inputs never reach the target, so passing an event means sending it ourselves.
+            // it is still the user's input, so it is not released by clear_* or on drop
+            for event in self.km_poll_physical()? {
+                match callback.call(event) {
+                    InterceptAction::Pass => self.km_forward_event(event)?,
+                    InterceptAction::Drop => {}
+                    InterceptAction::Replace(replacement) => self.km_forward_event(replacement)?,
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl ConnectionMonitor for InputFlowKMBox {
+    #[doc = r" Current state of the link to the device"]
+    fn connection_state(&self) -> ConnectionState {
+        self.state
+    }
+
+    #[doc = r" Calls `callback` once for every state change since the last poll."]
+    #[doc = r" Also attempts a reconnect when the device is gone and the backoff is over."]
+    fn poll_connection_changes(&mut self, mut callback: ConnectionCallback) -> Result<()> {
+        catch_panic(|| {
+            if self.state == ConnectionState::Disconnected {
+                // still gone is not an error of the poll
+                if self.km_reconnect().is_err() {
+                    take_last_error();
+                }
+            }
+
+            for state in std::mem::take(&mut self.state_changes) {
+                if !callback.call(state) {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+// =================================================================================================================
+// =================================== CGlue Plugin init and Header definitions ====================================
+// =================================================================================================================
+
+inputflow::define_plugin! {
+    controller: InputFlowKMBox,
+    args: args::Args,
+    arg_help: args::ARG_HELP,
+    create: InputFlowKMBox::new,
+    traits: { KeyboardWriter, KeyboardReader, MouseWriter, SmoothMouseWriter, MouseReader, InputInterceptor, EventWriter, ConnectionMonitor },
+    name: "kmbox",
+    description: "Sends mouse and keyboard input to an external computer through a KMBox serial device",
+    devices: discovery::device_descriptors,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BATCH: &[u8] =
+        b"km.left(1)\r\nkm.move(10,0)\r\nkm.wheel(1)\r\nkm.down(4)\r\nkm.move(0,10)\r\n";
+
+    #[test]
+    fn acked_commands() {
+        assert_eq!(km_acked_len(BATCH, b""), 0);
+        // echoed but not finished yet
+        assert_eq!(km_acked_len(BATCH, b"km.left(1)\r\n"), 0);
+        assert_eq!(
+            km_acked_len(
+                BATCH,
+                b"km.left(1)\r\n>>> km.move(10,0)\r\n>>> km.wheel(1)\r\n"
+            ),
+            "km.left(1)\r\nkm.move(10,0)\r\n".len()
+        );
+        let all = b"km.left(1)\r\n>>> km.move(10,0)\r\n>>> km.wheel(1)\r\n>>> km.down(4)\r\n>>> km.move(0,10)\r\n>>> ";
+        assert_eq!(km_acked_len(BATCH, all), BATCH.len());
+    }
+
+    #[test]
+    fn retries_do_not_repeat_moves() {
+        assert_eq!(km_retry_cmd(BATCH, 0), BATCH);
+        assert_eq!(
+            km_retry_cmd(
+                BATCH,
+                "km.left(1)\r\nkm.move(10,0)\r\nkm.wheel(1)\r\n".len()
+            ),
+            b"km.left(1)\r\nkm.down(4)\r\nkm.move(0,10)\r\n"
+        );
+        assert_eq!(
+            km_retry_cmd(BATCH, BATCH.len()),
+            b"km.left(1)\r\nkm.down(4)\r\n"
+        );
+    }
+}
@@ -0,0 +1,575 @@
+//! KMBox Net transport.
+//!
+//! KMBox Net units are controlled over UDP with binary packets instead of the `km.*` text protocol.
+//! Every packet starts with a header carrying the device uuid and a sequence number,
+//! the device acknowledges a packet by sending its header back.
+//! All fields are little endian.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use inputflow::prelude::*;
+
+use crate::keycodes::KMBoxKeyboardKeyCode;
+
+const CMD_CONNECT: u32 = 0xaf3c2828;
+const CMD_MOUSE_MOVE: u32 = 0xaede7345;
+const CMD_MOUSE_LEFT: u32 = 0x9823ae8d;
+const CMD_MOUSE_MIDDLE: u32 = 0x97a3ae8d;
+const CMD_MOUSE_RIGHT: u32 = 0x238d8212;
+const CMD_MOUSE_WHEEL: u32 = 0xffeead38;
+const CMD_MOUSE_AUTO_MOVE: u32 = 0xaede7346;
+const CMD_MOUSE_BEZIER: u32 = 0xa238455a;
+const CMD_KEYBOARD_ALL: u32 = 0x123c2c2f;
+
+/// uuid, random value, sequence number and command as `u32`
+const HEADER_LEN: usize = 16;
+/// buttons, x, y, wheel and ten bezier control points as `i32`
+const MOUSE_REPORT_LEN: usize = 56;
+/// modifier bits, a reserved byte and the codes of up to ten held keys
+const KEYBOARD_REPORT_LEN: usize = 12;
+const KEYBOARD_SLOTS: usize = 10;
+
+/// Connection to a KMBox Net unit
+#[derive(Debug)]
+pub(crate) struct KMBoxNet {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    /// device uuid shown on the KMBox screen
+    uuid: u32,
+    /// random value picked per connection
+    rand: u32,
+    /// sequence number of the last packet, echoed back by the device
+    index: u32,
+    /// held mouse buttons as KMBox button bits
+    buttons: i32,
+    /// held modifier keys as bits of the keyboard report
+    modifiers: u8,
+    /// held keys, free slots are zero
+    keys: [u8; KEYBOARD_SLOTS],
+    response_timeout: Duration,
+    fire_and_forget_moves: bool,
+}
+
+impl KMBoxNet {
+    /// Connects to the KMBox Net at `ip`:`port` and performs the handshake with `uuid`
+    pub(crate) fn connect(
+        ip: &str,
+        port: u16,
+        uuid: &str,
+        response_timeout: Duration,
+        fire_and_forget_moves: bool,
+    ) -> Result<Self> {
+        let uuid = parse_uuid(uuid).ok_or_else(|| {
+            InputFlowError::Parameter.with_message(format!(
+                "kmbox net uuid {uuid:?} is not an 8 digit hex number"
+            ))
+        })?;
+
+        let addr = (ip, port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| {
+                InputFlowError::Parameter
+                    .with_message(format!("{ip}:{port} is not a valid kmbox net address"))
+            })?;
+
+        let bind: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind)
+            .and_then(|socket| socket.connect(addr).map(|_| socket))
+            .map_err(|e| {
+                let message = format!("opening udp socket to {addr} failed: {e}");
+                InputFlowError::from(e).with_message(message)
+            })?;
+        // a zero read timeout is rejected by the socket
+        socket
+            .set_read_timeout(Some(response_timeout.max(Duration::from_millis(1))))
+            .map_err(InputFlowError::from)?;
+
+        let rand = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+
+        let mut net = Self {
+            socket,
+            addr,
+            uuid,
+            rand,
+            index: 0,
+            buttons: 0,
+            modifiers: 0,
+            keys: [0; KEYBOARD_SLOTS],
+            response_timeout,
+            fire_and_forget_moves,
+        };
+
+        net.send_packet(CMD_CONNECT, &[], true).inspect_err(|_| {
+            log::error!("kmbox net handshake with {addr} failed.");
+        })?;
+        log::info!("Connected to kmbox net at {addr}");
+        Ok(net)
+    }
+
+    /// Sends a single input event
+    pub(crate) fn send_event(&mut self, event: InputEvent) -> Result<()> {
+        match event {
+            InputEvent::KeyDown(key) | InputEvent::KeyUp(key) => {
+                let code = KMBoxKeyboardKeyCode::try_from(key)? as u8;
+                self.set_key(code, matches!(event, InputEvent::KeyDown(_)))
+            }
+            InputEvent::ButtonDown(button) | InputEvent::ButtonUp(button) => {
+                let down = matches!(event, InputEvent::ButtonDown(_));
+                match button {
+                    // the wheel has no held state, a scroll button turns one notch on press
+                    MouseButton::ScrollUp if down => self.wheel(1),
+                    MouseButton::ScrollDown if down => self.wheel(-1),
+                    MouseButton::ScrollUp | MouseButton::ScrollDown => Ok(()),
+                    _ => self.set_button(button, down),
+                }
+            }
+            InputEvent::MoveRelative { x, y } => {
+                let report = self.mouse_report(x, y, 0);
+                self.send_packet(CMD_MOUSE_MOVE, &report, !self.fire_and_forget_moves)
+            }
+            InputEvent::Wheel(notches) => self.wheel(notches),
+            InputEvent::MoveAbsolute { .. } => Err(InputFlowError::Unsupported),
+        }
+    }
+
+    /// Moves the mouse by `x`, `y` over `duration_ms`, along a bezier curve if `controls` are given.
+    /// The duration is passed in the random field of the header,
+    /// the control points in the first four point fields of the mouse report.
+    pub(crate) fn move_over(
+        &mut self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        controls: Option<BezierControls>,
+    ) -> Result<()> {
+        let mut report = self.mouse_report(x, y, 0);
+        let cmd = match controls {
+            Some(c) => {
+                for (i, value) in [c.x1, c.y1, c.x2, c.y2].into_iter().enumerate() {
+                    report[16 + i * 4..20 + i * 4].copy_from_slice(&value.to_le_bytes());
+                }
+                CMD_MOUSE_BEZIER
+            }
+            None => CMD_MOUSE_AUTO_MOVE,
+        };
+        self.send_packet_with_rand(cmd, duration_ms, &report, !self.fire_and_forget_moves)
+    }
+
+    fn set_button(&mut self, button: MouseButton, down: bool) -> Result<()> {
+        // side buttons have no command of their own, every button command carries the full button state
+        let (bit, cmd) = match button {
+            MouseButton::Left => (0x01, CMD_MOUSE_LEFT),
+            MouseButton::Right => (0x02, CMD_MOUSE_RIGHT),
+            MouseButton::Middle => (0x04, CMD_MOUSE_MIDDLE),
+            MouseButton::XButton1 => (0x08, CMD_MOUSE_LEFT),
+            MouseButton::XButton2 => (0x10, CMD_MOUSE_LEFT),
+            _ => return Err(InputFlowError::InvalidKey),
+        };
+
+        let previous = self.buttons;
+        if down {
+            self.buttons |= bit;
+        } else {
+            self.buttons &= !bit;
+        }
+
+        let report = self.mouse_report(0, 0, 0);
+        self.send_packet(cmd, &report, true)
+            .inspect_err(|_| self.buttons = previous)
+    }
+
+    fn wheel(&mut self, notches: i32) -> Result<()> {
+        let report = self.mouse_report(0, 0, notches);
+        self.send_packet(CMD_MOUSE_WHEEL, &report, true)
+    }
+
+    fn set_key(&mut self, code: u8, down: bool) -> Result<()> {
+        let (previous_modifiers, previous_keys) = (self.modifiers, self.keys);
+
+        if let 0xE0..=0xE7 = code {
+            let bit = 1 << (code - 0xE0);
+            if down {
+                self.modifiers |= bit;
+            } else {
+                self.modifiers &= !bit;
+            }
+        } else if down {
+            if !self.keys.contains(&code) {
+                let slot = self.keys.iter_mut().find(|k| **k == 0).ok_or_else(|| {
+                    InputFlowError::SendError.with_message(format!(
+                        "kmbox net can not hold more than {KEYBOARD_SLOTS} keys at once"
+                    ))
+                })?;
+                *slot = code;
+            }
+        } else {
+            self.keys
+                .iter_mut()
+                .filter(|k| **k == code)
+                .for_each(|k| *k = 0);
+        }
+
+        let mut report = [0u8; KEYBOARD_REPORT_LEN];
+        report[0] = self.modifiers;
+        report[2..].copy_from_slice(&self.keys);
+        self.send_packet(CMD_KEYBOARD_ALL, &report, true)
+            .inspect_err(|_| {
+                self.modifiers = previous_modifiers;
+                self.keys = previous_keys;
+            })
+    }
+
+    fn mouse_report(&self, x: i32, y: i32, wheel: i32) -> [u8; MOUSE_REPORT_LEN] {
+        let mut report = [0u8; MOUSE_REPORT_LEN];
+        for (i, value) in [self.buttons, x, y, wheel].into_iter().enumerate() {
+            report[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        report
+    }
+
+    /// Sends a packet and, if `acknowledged`, waits for the device to echo its header
+    fn send_packet(&mut self, cmd: u32, payload: &[u8], acknowledged: bool) -> Result<()> {
+        self.send_packet_with_rand(cmd, self.rand, payload, acknowledged)
+    }
+
+    /// Like [`Self::send_packet`], with a command specific value in the random field of the header
+    fn send_packet_with_rand(
+        &mut self,
+        cmd: u32,
+        rand: u32,
+        payload: &[u8],
+        acknowledged: bool,
+    ) -> Result<()> {
+        if cmd != CMD_CONNECT {
+            self.index = self.index.wrapping_add(1);
+        }
+
+        let mut packet = Vec::with_capacity(HEADER_LEN + payload.len());
+        for value in [self.uuid, rand, self.index, cmd] {
+            packet.extend_from_slice(&value.to_le_bytes());
+        }
+        packet.extend_from_slice(payload);
+
+        self.socket.send(&packet).map_err(|e| {
+            log::warn!("sending kmbox net packet {cmd:#x} failed: {e:?}.");
+            let message = format!("sending to kmbox net {} failed: {e}", self.addr);
+            InputFlowError::from(e).with_message(message)
+        })?;
+
+        if acknowledged {
+            self.read_ack(cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the echo of the packet with the current sequence number,
+    /// late acknowledgements of earlier packets are skipped
+    fn read_ack(&mut self, cmd: u32) -> Result<()> {
+        let deadline = Instant::now() + self.response_timeout;
+        let mut buf = [0u8; 1024];
+
+        loop {
+            let timed_out = || {
+                log::warn!("kmbox net {} did not acknowledge {cmd:#x}.", self.addr);
+                InputFlowError::Timeout.with_message(format!(
+                    "no answer from kmbox net {} within {:?}, check the ip, port and uuid",
+                    self.addr, self.response_timeout
+                ))
+            };
+
+            match self.socket.recv(&mut buf) {
+                Ok(n) if n >= HEADER_LEN => {
+                    let field = |i: usize| {
+                        u32::from_le_bytes(buf[i * 4..i * 4 + 4].try_into().unwrap_or_default())
+                    };
+                    if field(2) == self.index && field(3) == cmd {
+                        return Ok(());
+                    }
+                }
+                Ok(_) => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Err(timed_out());
+                }
+                Err(e) => {
+                    log::warn!("reading kmbox net acknowledgement failed: {e:?}.");
+                    let message = format!("reading from kmbox net {} failed: {e}", self.addr);
+                    return Err(InputFlowError::from(e).with_message(message));
+                }
+            }
+
+            if Instant::now() > deadline {
+                return Err(timed_out());
+            }
+        }
+    }
+}
+
+/// Parses the uuid shown on the KMBox screen, 8 hex digits
+fn parse_uuid(uuid: &str) -> Option<u32> {
+    let uuid = uuid.trim();
+    if uuid.is_empty() || uuid.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(uuid, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local stand-in for a KMBox Net that acknowledges every packet and hands it to the test
+    struct StandIn {
+        socket: UdpSocket,
+    }
+
+    impl StandIn {
+        fn new() -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            Self { socket }
+        }
+
+        fn port(&self) -> u16 {
+            self.socket.local_addr().unwrap().port()
+        }
+
+        /// Receives the next packet, acknowledges it and returns it
+        fn ack(&self) -> Vec<u8> {
+            let mut buf = [0u8; 1024];
+            let (n, from) = self.socket.recv_from(&mut buf).unwrap();
+            self.socket.send_to(&buf[..HEADER_LEN], from).unwrap();
+            buf[..n].to_vec()
+        }
+
+        /// Receives the next packet without answering
+        fn ignore(&self) -> Vec<u8> {
+            let mut buf = [0u8; 1024];
+            let (n, _) = self.socket.recv_from(&mut buf).unwrap();
+            buf[..n].to_vec()
+        }
+    }
+
+    fn field(packet: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes(packet[i * 4..i * 4 + 4].try_into().unwrap())
+    }
+
+    fn connect(stand_in: &StandIn, fire_and_forget_moves: bool) -> KMBoxNet {
+        let port = stand_in.port();
+        let handshake = std::thread::scope(|s| {
+            let device = s.spawn(|| stand_in.ack());
+            let net = KMBoxNet::connect(
+                "127.0.0.1",
+                port,
+                "1a2b3c4d",
+                Duration::from_millis(500),
+                fire_and_forget_moves,
+            )
+            .unwrap();
+            (net, device.join().unwrap())
+        });
+
+        let (net, packet) = handshake;
+        assert_eq!(packet.len(), HEADER_LEN);
+        assert_eq!(field(&packet, 0), 0x1a2b3c4d);
+        assert_eq!(field(&packet, 2), 0);
+        assert_eq!(field(&packet, 3), CMD_CONNECT);
+        net
+    }
+
+    /// Sends `event` while the stand-in acknowledges it and returns the packet the device got
+    fn send(stand_in: &StandIn, net: &mut KMBoxNet, event: InputEvent) -> Vec<u8> {
+        std::thread::scope(|s| {
+            let device = s.spawn(|| stand_in.ack());
+            net.send_event(event).unwrap();
+            device.join().unwrap()
+        })
+    }
+
+    #[test]
+    fn mouse_packets() {
+        let stand_in = StandIn::new();
+        let mut net = connect(&stand_in, false);
+
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::MoveRelative { x: -5, y: 7 },
+        );
+        assert_eq!(packet.len(), HEADER_LEN + MOUSE_REPORT_LEN);
+        assert_eq!(field(&packet, 2), 1);
+        assert_eq!(field(&packet, 3), CMD_MOUSE_MOVE);
+        assert_eq!(field(&packet, 5) as i32, -5);
+        assert_eq!(field(&packet, 6) as i32, 7);
+
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::ButtonDown(MouseButton::Right),
+        );
+        assert_eq!(field(&packet, 3), CMD_MOUSE_RIGHT);
+        assert_eq!(field(&packet, 4), 0x02);
+
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::ButtonDown(MouseButton::Left),
+        );
+        assert_eq!(field(&packet, 3), CMD_MOUSE_LEFT);
+        assert_eq!(field(&packet, 4), 0x03);
+
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::ButtonUp(MouseButton::Right),
+        );
+        assert_eq!(field(&packet, 4), 0x01);
+
+        let packet = send(&stand_in, &mut net, InputEvent::Wheel(-2));
+        assert_eq!(field(&packet, 2), 5);
+        assert_eq!(field(&packet, 3), CMD_MOUSE_WHEEL);
+        assert_eq!(field(&packet, 4), 0x01);
+        assert_eq!(field(&packet, 7) as i32, -2);
+    }
+
+    #[test]
+    fn smooth_moves() {
+        let stand_in = StandIn::new();
+        let mut net = connect(&stand_in, false);
+
+        let packet = std::thread::scope(|s| {
+            let device = s.spawn(|| stand_in.ack());
+            net.move_over(10, -20, 150, None).unwrap();
+            device.join().unwrap()
+        });
+        assert_eq!(field(&packet, 1), 150);
+        assert_eq!(field(&packet, 3), CMD_MOUSE_AUTO_MOVE);
+        assert_eq!(field(&packet, 5) as i32, 10);
+        assert_eq!(field(&packet, 6) as i32, -20);
+        assert!(packet[HEADER_LEN + 16..].iter().all(|b| *b == 0));
+
+        let controls = BezierControls {
+            x1: 1,
+            y1: 2,
+            x2: 3,
+            y2: -4,
+        };
+        let packet = std::thread::scope(|s| {
+            let device = s.spawn(|| stand_in.ack());
+            net.move_over(10, -20, 150, Some(controls)).unwrap();
+            device.join().unwrap()
+        });
+        assert_eq!(field(&packet, 1), 150);
+        assert_eq!(field(&packet, 3), CMD_MOUSE_BEZIER);
+        assert_eq!(field(&packet, 5) as i32, 10);
+        assert_eq!(field(&packet, 6) as i32, -20);
+        let points: Vec<_> = (8..18).map(|i| field(&packet, i) as i32).collect();
+        assert_eq!(points, [1, 2, 3, -4, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn keyboard_packets() {
+        let stand_in = StandIn::new();
+        let mut net = connect(&stand_in, false);
+
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::KeyDown(KeyboardKey::LSHIFT),
+        );
+        assert_eq!(packet.len(), HEADER_LEN + KEYBOARD_REPORT_LEN);
+        assert_eq!(field(&packet, 3), CMD_KEYBOARD_ALL);
+        assert_eq!(
+            packet[HEADER_LEN..],
+            [0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let packet = send(&stand_in, &mut net, InputEvent::KeyDown(KeyboardKey::A));
+        assert_eq!(
+            packet[HEADER_LEN..],
+            [0x02, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let packet = send(&stand_in, &mut net, InputEvent::KeyDown(KeyboardKey::B));
+        assert_eq!(
+            packet[HEADER_LEN..],
+            [0x02, 0, 0x04, 0x05, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let packet = send(&stand_in, &mut net, InputEvent::KeyUp(KeyboardKey::A));
+        assert_eq!(
+            packet[HEADER_LEN..],
+            [0x02, 0, 0, 0x05, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let packet = send(&stand_in, &mut net, InputEvent::KeyUp(KeyboardKey::LSHIFT));
+        assert_eq!(
+            packet[HEADER_LEN..],
+            [0, 0, 0, 0x05, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn unanswered_packets_time_out() {
+        let stand_in = StandIn::new();
+        let mut net = connect(&stand_in, false);
+
+        let result = std::thread::scope(|s| {
+            let device = s.spawn(|| stand_in.ignore());
+            let result = net.send_event(InputEvent::ButtonDown(MouseButton::Left));
+            device.join().unwrap();
+            result
+        });
+        assert_eq!(result, Err(InputFlowError::Timeout));
+        // the button was never confirmed, so it is not sent as held with the next packet
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::ButtonDown(MouseButton::Right),
+        );
+        assert_eq!(field(&packet, 4), 0x02);
+    }
+
+    #[test]
+    fn fire_and_forget_moves() {
+        let stand_in = StandIn::new();
+        let mut net = connect(&stand_in, true);
+
+        net.send_event(InputEvent::MoveRelative { x: 1, y: 1 })
+            .unwrap();
+        let packet = stand_in.ignore();
+        assert_eq!(field(&packet, 3), CMD_MOUSE_MOVE);
+
+        // acknowledged packets still wait for their own echo
+        let packet = send(
+            &stand_in,
+            &mut net,
+            InputEvent::ButtonDown(MouseButton::Left),
+        );
+        assert_eq!(field(&packet, 2), 2);
+    }
+
+    #[test]
+    fn invalid_uuid() {
+        assert_eq!(parse_uuid("1A2B3C4D"), Some(0x1a2b3c4d));
+        assert_eq!(parse_uuid(""), None);
+        assert_eq!(parse_uuid("123456789"), None);
+        assert_eq!(parse_uuid("xyz"), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! Byte streams the `km.*` text protocol runs over.
+//!
+//! The plugin opens a serial port or a TCP connection itself, depending on its arguments.
+//! Other transports such as a pty pair or [`MemoryTransport`] can be handed in through
+//! [`crate::InputFlowKMBox::with_transport`], which is how the plugin is tested without a device.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use serialport::{ClearBuffer, SerialPort};
+
+/// How long establishing a TCP connection may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A bidirectional byte stream to a KMBox.
+///
+/// Reads must not block forever: once nothing arrived for the transport's timeout they fail with
+/// [`io::ErrorKind::TimedOut`], a peer that went away is reported as
+/// [`io::ErrorKind::BrokenPipe`] or a similar disconnect kind.
+pub trait Transport: Read + Write + Send + std::fmt::Debug {
+    /// Drops everything received that was not read yet
+    fn clear_input(&mut self) -> io::Result<()>;
+
+    /// Changes how long a read waits for data before failing with [`io::ErrorKind::TimedOut`]
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.clear(ClearBuffer::Input).map_err(io::Error::from)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_timeout(timeout).map_err(io::Error::from)
+    }
+}
+
+/// Either end of a pseudo terminal pair created with [`serialport::TTYPort::pair`]
+#[cfg(unix)]
+impl Transport for serialport::TTYPort {
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.clear(ClearBuffer::Input).map_err(io::Error::from)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_timeout(timeout).map_err(io::Error::from)
+    }
+}
+
+/// `km.*` commands over TCP, e.g. to a serial to network bridge or the emulator
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    /// Connects to `addr` (`host:port`).
+    /// Reads wait up to `timeout` for data, zero polls like a serial port without timeout.
+    pub fn connect(addr: &str, timeout: Duration) -> io::Result<Self> {
+        let mut last_error = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => return Self::from_stream(stream, timeout),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{addr} did not resolve to any address"),
+            )
+        }))
+    }
+
+    fn from_stream(stream: TcpStream, timeout: Duration) -> io::Result<Self> {
+        // commands are tiny and latency matters more than packet count
+        stream.set_nodelay(true)?;
+        let mut transport = Self { stream };
+        transport.set_read_timeout(timeout)?;
+        Ok(transport)
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            // the peer closed the connection
+            Ok(0) if !buf.is_empty() => Err(io::ErrorKind::UnexpectedEof.into()),
+            // unix reports an expired read timeout as would block
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            result => result,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0u8; 256];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // a zero read timeout is rejected by the socket
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+    }
+}
+
+/// One direction of a [`MemoryTransport`] pair
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PipeState {
+    data: VecDeque<u8>,
+    /// one of the ends was dropped
+    closed: bool,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// In-memory transport, one end of a pair where each end reads what the other one wrote.
+///
+/// Dropping one end makes the other one fail like an unplugged device once it read everything.
+#[derive(Debug)]
+pub struct MemoryTransport {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+    timeout: Duration,
+}
+
+impl MemoryTransport {
+    /// Creates two connected ends whose reads wait up to `timeout` for data
+    pub fn pair(timeout: Duration) -> (Self, Self) {
+        let a = Arc::new(Pipe::default());
+        let b = Arc::new(Pipe::default());
+        (
+            Self {
+                rx: a.clone(),
+                tx: b.clone(),
+                timeout,
+            },
+            Self {
+                rx: b,
+                tx: a,
+                timeout,
+            },
+        )
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = self.rx.state.lock().unwrap();
+        let (mut state, _) = self
+            .rx
+            .changed
+            .wait_timeout_while(state, self.timeout, |s| s.data.is_empty() && !s.closed)
+            .unwrap();
+
+        if state.data.is_empty() {
+            return Err(if state.closed {
+                io::ErrorKind::BrokenPipe.into()
+            } else {
+                io::ErrorKind::TimedOut.into()
+            });
+        }
+
+        let n = buf.len().min(state.data.len());
+        for (dst, src) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.tx.state.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.data.extend(buf);
+        self.tx.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.rx.state.lock().unwrap().data.clear();
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn read_all(transport: &mut impl Transport) -> io::Result<Vec<u8>> {
+        let mut buf = [0u8; 64];
+        let n = transport.read(&mut buf)?;
+        Ok(buf[..n].to_vec())
+    }
+
+    #[test]
+    fn memory_pair() {
+        let (mut host, mut device) = MemoryTransport::pair(Duration::from_millis(10));
+
+        host.write_all(b"km.move(1,1)\r\n").unwrap();
+        assert_eq!(read_all(&mut device).unwrap(), b"km.move(1,1)\r\n");
+        assert_eq!(
+            read_all(&mut device).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+        device.set_read_timeout(Duration::ZERO).unwrap();
+        assert_eq!(
+            read_all(&mut device).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+
+        device.write_all(b"stale").unwrap();
+        host.clear_input().unwrap();
+        device.write_all(b">>> ").unwrap();
+        drop(device);
+        assert_eq!(read_all(&mut host).unwrap(), b">>> ");
+        assert_eq!(
+            read_all(&mut host).unwrap_err().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+        assert!(host.write_all(b"km.left(1)\r\n").is_err());
+    }
+
+    #[test]
+    fn tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut host = TcpTransport::connect(&addr, Duration::from_millis(10)).unwrap();
+        let (mut device, _) = listener.accept().unwrap();
+
+        host.write_all(b"km.left(1)\r\n").unwrap();
+        let mut buf = [0u8; 12];
+        device.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"km.left(1)\r\n");
+
+        assert_eq!(
+            read_all(&mut host).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+
+        device.write_all(b"stale").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        host.clear_input().unwrap();
+        device.write_all(b">>> ").unwrap();
+        assert_eq!(read_all(&mut host).unwrap(), b">>> ");
+
+        drop(device);
+        assert_eq!(
+            read_all(&mut host).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
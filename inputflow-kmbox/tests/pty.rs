@@ -0,0 +1,485 @@
+//! Drives `InputFlowKMBox` through a pseudo terminal against the KMBox emulator, checks the exact
+//! `km.*` bytes it writes and what they did to the emulated target.
+//! Reconnects are tested over tcp, which unlike a pty handed in by the test can be opened again.
+#![cfg(unix)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use inputflow::prelude::*;
+use inputflow_kmbox::InputFlowKMBox;
+use inputflow_kmbox_emulator::{serve, Behavior, Cursor, DeviceState, Emulator};
+use serialport::{SerialPort, TTYPort};
+
+/// The device end of the pty
+struct Device {
+    emulator: Arc<Mutex<Emulator>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Device {
+    /// Everything written since the last call
+    fn take(&self) -> String {
+        self.with(Emulator::take_received)
+    }
+
+    fn state(&self) -> DeviceState {
+        self.with(|emulator| emulator.state().clone())
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Emulator) -> T) -> T {
+        f(&mut self.emulator.lock().unwrap())
+    }
+
+    fn unplug(&mut self) {
+        self.with(Emulator::unplug);
+        self.thread.take().unwrap().join().unwrap();
+    }
+
+    /// Unplugs a device served over tcp and plugs in a fresh one with nothing held or masked
+    fn replug(&self) {
+        self.with(Emulator::unplug);
+        // the device notices within its read timeout and closes the connection
+        thread::sleep(Duration::from_millis(50));
+        self.with(|emulator| *emulator = Emulator::new());
+    }
+
+    /// Waits for the plugin to go away and returns what it wrote last
+    fn finish(mut self) -> String {
+        self.thread.take().unwrap().join().unwrap();
+        self.take()
+    }
+}
+
+fn start(behavior: Behavior) -> (InputFlowKMBox, Device) {
+    start_with(behavior, "response_timeout_ms=200")
+}
+
+fn start_with(behavior: Behavior, args: &str) -> (InputFlowKMBox, Device) {
+    let (mut master, mut slave) = TTYPort::pair().unwrap();
+    master.set_timeout(Duration::from_millis(10)).unwrap();
+    slave.set_timeout(Duration::from_millis(10)).unwrap();
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    emulator.lock().unwrap().set_behavior(behavior);
+    let thread = {
+        let emulator = emulator.clone();
+        thread::spawn(move || {
+            // ends with an error once the plugin closes its end or the device is unplugged,
+            // dropping the master end closes the pty
+            let _ = serve(&emulator, &mut master, |_| {});
+        })
+    };
+
+    let kmbox = InputFlowKMBox::with_transport(slave, args).unwrap();
+    (
+        kmbox,
+        Device {
+            emulator,
+            thread: Some(thread),
+        },
+    )
+}
+
+/// Serves the emulator on a local tcp port, every connection the plugin makes reaches the same device
+fn start_tcp(behavior: Behavior, args: &str) -> (InputFlowKMBox, Device) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let emulator = Arc::new(Mutex::new(Emulator::new()));
+    emulator.lock().unwrap().set_behavior(behavior);
+    {
+        let emulator = emulator.clone();
+        // accepts until the test ends
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_millis(10)))
+                    .unwrap();
+                let _ = serve(&emulator, &mut stream, |_| {});
+            }
+        });
+    }
+
+    let kmbox = InputFlowKMBox::connect(&format!("tcp_addr={addr},{args}")).unwrap();
+    (
+        kmbox,
+        Device {
+            emulator,
+            thread: None,
+        },
+    )
+}
+
+fn connection_changes(kmbox: &mut InputFlowKMBox) -> Vec<ConnectionState> {
+    let mut changes = Vec::new();
+    kmbox
+        .poll_connection_changes(
+            (&mut |state| {
+                changes.push(state);
+                true
+            })
+                .into(),
+        )
+        .unwrap();
+    changes
+}
+
+#[test]
+fn mouse_moves() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.mouse_move_relative(10, -5).unwrap();
+    assert_eq!(device.take(), "km.move(10,-5)\r\n");
+
+    kmbox.move_relative_over(100, 0, 30).unwrap();
+    assert_eq!(device.take(), "km.move(100,0,30)\r\n");
+
+    let controls = BezierControls {
+        x1: 10,
+        y1: 20,
+        x2: 30,
+        y2: 40,
+    };
+    kmbox.move_bezier_over(50, 60, 30, controls).unwrap();
+    assert_eq!(device.take(), "km.move(50,60,30,10,20,30,40)\r\n");
+
+    assert_eq!(device.state().cursor, Cursor { x: 160, y: 55 });
+}
+
+#[test]
+fn mouse_buttons() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.send_button_down(MouseButton::Right).unwrap();
+    kmbox.send_button_up(MouseButton::Right).unwrap();
+    assert_eq!(device.take(), "km.right(1)\r\nkm.right(0)\r\n");
+
+    // press and release go out in a single write
+    kmbox.click_button(MouseButton::Middle).unwrap();
+    assert_eq!(device.take(), "km.middle(1)\r\nkm.middle(0)\r\n");
+
+    kmbox.click_button(MouseButton::ScrollDown).unwrap();
+    assert_eq!(device.take(), "km.wheel(-1)\r\n");
+
+    kmbox.send_button_down(MouseButton::Left).unwrap();
+    device.take();
+    let state = device.state();
+    assert!(state.buttons.left && !state.buttons.right && !state.buttons.middle);
+    assert_eq!(state.wheel, -1);
+}
+
+#[test]
+fn keyboard_keys() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.send_key_down(KeyboardKey::A).unwrap();
+    kmbox.send_key_up(KeyboardKey::A).unwrap();
+    assert_eq!(device.take(), "km.down(4)\r\nkm.up(4)\r\n");
+
+    kmbox.press_key(KeyboardKey::B).unwrap();
+    assert_eq!(device.take(), "km.press(5,15,50)\r\n");
+
+    let state = device.state();
+    assert!(state.keys.is_empty());
+    assert_eq!(state.key_presses, BTreeMap::from([(0x05, 1)]));
+}
+
+#[test]
+fn batched_events_are_one_write() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox
+        .send_events(&[
+            InputEvent::KeyDown(KeyboardKey::A),
+            InputEvent::MoveRelative { x: 1, y: 2 },
+            InputEvent::ButtonDown(MouseButton::Left),
+            InputEvent::Wheel(3),
+            InputEvent::KeyUp(KeyboardKey::A),
+        ])
+        .unwrap();
+    assert_eq!(
+        device.take(),
+        "km.down(4)\r\nkm.move(1,2)\r\nkm.left(1)\r\nkm.wheel(3)\r\nkm.up(4)\r\n"
+    );
+
+    let state = device.state();
+    assert_eq!(state.cursor, Cursor { x: 1, y: 2 });
+    assert!(state.buttons.left && state.keys.is_empty());
+    assert_eq!(state.wheel, 3);
+}
+
+#[test]
+fn queries() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    assert!(!kmbox.is_button_down(MouseButton::Left).unwrap());
+    assert_eq!(device.take(), "km.left()\r\n");
+
+    assert!(!kmbox.is_key_down(KeyboardKey::A).unwrap());
+    assert_eq!(device.take(), "km.isdown(4)\r\n");
+
+    // the physical mouse and keyboard are queried, not what this controller pressed
+    kmbox.send_button_down(MouseButton::Left).unwrap();
+    device.with(|emulator| emulator.physical_keyboard(&[0x04]));
+    assert!(!kmbox.is_button_down(MouseButton::Left).unwrap());
+    assert!(kmbox.is_key_down(KeyboardKey::A).unwrap());
+    assert!(!kmbox.is_key_down(KeyboardKey::B).unwrap());
+}
+
+#[test]
+fn held_input_is_released() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.send_button_down(MouseButton::Left).unwrap();
+    kmbox.clear_buttons().unwrap();
+    assert_eq!(device.take(), "km.left(1)\r\nkm.left(0)\r\n");
+
+    kmbox.send_key_down(KeyboardKey::C).unwrap();
+    kmbox.send_button_down(MouseButton::XButton1).unwrap();
+    device.take();
+
+    drop(kmbox);
+    let emulator = device.emulator.clone();
+    let released = device.finish();
+    assert!(released.contains("km.up(6)\r\n"), "{released:?}");
+    assert!(released.contains("km.side1(0)\r\n"), "{released:?}");
+
+    let emulator = emulator.lock().unwrap();
+    let state = emulator.state();
+    assert!(state.keys.is_empty());
+    assert!(!state.buttons.left && !state.buttons.side1);
+}
+
+#[test]
+fn intercepted_inputs_are_polled() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.intercept_button(MouseButton::Left, true).unwrap();
+    kmbox.intercept_key(KeyboardKey::A, true).unwrap();
+    assert_eq!(device.take(), "km.mask_left(1)\r\nkm.mask_keyboard(4)\r\n");
+
+    // left with right and shift+a, only left and a are intercepted
+    device.with(|emulator| {
+        emulator.physical_mouse(0x03);
+        emulator.physical_keyboard(&[0xE1, 0x04]);
+    });
+    // the masked inputs did not reach the target
+    let state = device.state();
+    assert!(state.buttons.right && !state.buttons.left);
+    assert_eq!(state.keys, BTreeSet::from([0xE1]));
+
+    let mut seen = Vec::new();
+    let mut a_to_b = |event| {
+        seen.push(event);
+        match event {
+            InputEvent::KeyDown(KeyboardKey::A) => {
+                InterceptAction::Replace(InputEvent::KeyDown(KeyboardKey::B))
+            }
+            InputEvent::KeyUp(KeyboardKey::A) => {
+                InterceptAction::Replace(InputEvent::KeyUp(KeyboardKey::B))
+            }
+            _ => InterceptAction::Pass,
+        }
+    };
+    kmbox.poll_intercepted((&mut a_to_b).into()).unwrap();
+    assert_eq!(
+        device.take(),
+        "km.left()\r\nkm.isdown(4)\r\nkm.left(1)\r\nkm.down(5)\r\n"
+    );
+    let state = device.state();
+    assert!(state.buttons.left);
+    assert_eq!(state.keys, BTreeSet::from([0x05, 0xE1]));
+
+    // nothing changed since the last poll
+    kmbox.poll_intercepted((&mut a_to_b).into()).unwrap();
+    assert_eq!(device.take(), "km.left()\r\nkm.isdown(4)\r\n");
+
+    device.with(|emulator| {
+        emulator.physical_mouse(0x02);
+        emulator.physical_keyboard(&[0xE1]);
+    });
+    kmbox.poll_intercepted((&mut a_to_b).into()).unwrap();
+    assert_eq!(
+        device.take(),
+        "km.left()\r\nkm.isdown(4)\r\nkm.left(0)\r\nkm.up(5)\r\n"
+    );
+    assert_eq!(
+        seen,
+        [
+            InputEvent::ButtonDown(MouseButton::Left),
+            InputEvent::KeyDown(KeyboardKey::A),
+            InputEvent::ButtonUp(MouseButton::Left),
+            InputEvent::KeyUp(KeyboardKey::A),
+        ]
+    );
+    assert_eq!(device.state().keys, BTreeSet::from([0xE1]));
+
+    // dropped inputs are not sent
+    device.with(|emulator| emulator.physical_mouse(0x01));
+    kmbox
+        .poll_intercepted((&mut |_| InterceptAction::Drop).into())
+        .unwrap();
+    assert_eq!(device.take(), "km.left()\r\nkm.isdown(4)\r\n");
+    assert!(!device.state().buttons.left);
+
+    kmbox.clear_intercepts().unwrap();
+    assert_eq!(device.take(), "km.unmask_all()\r\n");
+}
+
+#[test]
+fn passed_inputs_are_not_released_by_the_controller() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    kmbox.intercept_button(MouseButton::Left, true).unwrap();
+    device.with(|emulator| emulator.physical_mouse(0x01));
+    kmbox
+        .poll_intercepted((&mut |_| InterceptAction::Pass).into())
+        .unwrap();
+    assert_eq!(
+        device.take(),
+        "km.mask_left(1)\r\nkm.left()\r\nkm.left(1)\r\n"
+    );
+
+    // the user still holds the button, it is not this controller's to release
+    kmbox.clear_buttons().unwrap();
+    assert_eq!(device.take(), "");
+    assert!(device.state().buttons.left);
+
+    drop(kmbox);
+    assert_eq!(device.finish(), "km.unmask_all()\r\n");
+}
+
+#[test]
+fn fire_and_forget_moves() {
+    let (mut kmbox, device) = start_with(
+        Behavior::Silent,
+        "response_timeout_ms=50,fire_and_forget_moves=true",
+    );
+
+    kmbox.mouse_move_relative(1, 1).unwrap();
+    kmbox.mouse_move_relative(-1, 0).unwrap();
+    // only the written bytes are checked, the device never acknowledges
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(device.take(), "km.move(1,1)\r\nkm.move(-1,0)\r\n");
+}
+
+#[test]
+fn silent_device_disconnects() {
+    let (mut kmbox, device) = start_with(Behavior::Silent, "response_timeout_ms=50");
+
+    // the command times out and the probe after it too
+    assert_eq!(
+        kmbox.send_button_down(MouseButton::Left),
+        Err(InputFlowError::Disconnected)
+    );
+    assert!(take_last_error().unwrap().contains("cannot be reopened"));
+    assert_eq!(device.take(), "km.left(1)\r\n\r\n");
+    assert_eq!(kmbox.connection_state(), ConnectionState::Disconnected);
+}
+
+#[test]
+fn rejected_commands_fail() {
+    let (mut kmbox, device) = start(Behavior::Rejecting);
+
+    assert_eq!(
+        kmbox.send_key_down(KeyboardKey::A),
+        Err(InputFlowError::SendError)
+    );
+    assert!(take_last_error().unwrap().contains("NameError"));
+    assert_eq!(device.take(), "km.down(4)\r\n");
+}
+
+#[test]
+fn unknown_commands_fail() {
+    let (mut kmbox, device) = start(Behavior::Kmbox);
+
+    assert_eq!(
+        kmbox.km_query(b"km.bogus()\r\n"),
+        Err(InputFlowError::SendError)
+    );
+    assert!(take_last_error().unwrap().contains("AttributeError"));
+
+    // the device is still usable afterwards
+    kmbox.mouse_move_relative(1, 1).unwrap();
+    assert_eq!(device.take(), "km.bogus()\r\nkm.move(1,1)\r\n");
+    assert_eq!(device.state().commands, 2);
+}
+
+#[test]
+fn unplugged_device_disconnects() {
+    let (mut kmbox, mut device) = start(Behavior::Kmbox);
+
+    kmbox.mouse_move_relative(1, 0).unwrap();
+    assert_eq!(kmbox.connection_state(), ConnectionState::Connected);
+
+    device.unplug();
+    assert_eq!(
+        kmbox.mouse_move_relative(1, 0),
+        Err(InputFlowError::Disconnected)
+    );
+    assert_eq!(kmbox.connection_state(), ConnectionState::Disconnected);
+    assert_eq!(
+        connection_changes(&mut kmbox),
+        [ConnectionState::Disconnected]
+    );
+}
+
+#[test]
+fn replugged_device_gets_held_and_masked_input_back() {
+    let (mut kmbox, device) = start_tcp(Behavior::Kmbox, "response_timeout_ms=200");
+
+    kmbox.send_button_down(MouseButton::Left).unwrap();
+    kmbox.send_key_down(KeyboardKey::A).unwrap();
+    kmbox.intercept_key(KeyboardKey::B, true).unwrap();
+    device.take();
+
+    device.replug();
+    kmbox.mouse_move_relative(1, 0).unwrap();
+    assert_eq!(
+        device.take(),
+        "km.left(1)\r\nkm.down(4)\r\nkm.mask_keyboard(5)\r\nkm.move(1,0)\r\n"
+    );
+
+    // the key is masked again on the new device
+    device.with(|emulator| emulator.physical_keyboard(&[0x05]));
+    let state = device.state();
+    assert!(state.buttons.left);
+    assert_eq!(state.keys, BTreeSet::from([0x04]));
+    assert_eq!(state.cursor, Cursor { x: 1, y: 0 });
+
+    assert_eq!(
+        connection_changes(&mut kmbox),
+        [ConnectionState::Disconnected, ConnectionState::Connected]
+    );
+}
+
+#[test]
+fn hung_device_disconnects_until_it_answers_again() {
+    let (mut kmbox, device) = start_tcp(Behavior::Silent, "response_timeout_ms=50");
+
+    // the port reopens, but the device does not answer the probe after it either
+    assert_eq!(
+        kmbox.mouse_move_relative(1, 0),
+        Err(InputFlowError::Disconnected)
+    );
+    assert!(take_last_error().unwrap().contains("reconnecting failed"));
+    assert_eq!(device.take(), "km.move(1,0)\r\n\r\n\r\n");
+    assert_eq!(kmbox.connection_state(), ConnectionState::Disconnected);
+
+    device.with(|emulator| emulator.set_behavior(Behavior::Kmbox));
+    // past the backoff of the failed reconnect
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(
+        connection_changes(&mut kmbox),
+        [ConnectionState::Disconnected, ConnectionState::Connected]
+    );
+    assert_eq!(device.take(), "\r\n");
+
+    kmbox.mouse_move_relative(1, 0).unwrap();
+    assert_eq!(device.state().cursor, Cursor { x: 1, y: 0 });
+}
@@ -6,35 +6,31 @@
 use enigo::{Button, Direction, Enigo, Keyboard, Mouse, Settings};
 use inputflow::prelude::*;
 
-#[derive(Default)]
-struct NativePluginRoot {
-    controller: InputFlowNative,
+#[derive(Debug)]
+pub struct InputFlowNative {
+    /// None if enigo could not be set up, every call then fails with [`InputFlowError::Uninitialized`]
+    enigo: Option<Enigo>,
 }
 
-impl<'a> PluginInner<'a> for NativePluginRoot {
-    type BorrowedType = Fwd<&'a mut InputFlowNative>;
-
-    type OwnedType = InputFlowNative;
-    type OwnedTypeMut = InputFlowNative;
-
-    fn borrow_features(&'a mut self) -> Self::BorrowedType {
-        self.controller.forward_mut()
-    }
-
-    fn into_features(self) -> Self::OwnedType {
-        self.controller
+impl InputFlowNative {
+    fn new() -> Result<Self> {
+        let enigo = Enigo::new(&Settings {
+            release_keys_when_dropped: true,
+            ..Default::default()
+        })
+        .map_err(|e| {
+            InputFlowError::Uninitialized.with_message(format!("setting up enigo failed: {e}"))
+        })?;
+        Ok(Self { enigo: Some(enigo) })
     }
 
-    fn mut_features(&'a mut self) -> &'a mut Self::OwnedTypeMut {
-        &mut self.controller
+    fn enigo(&mut self) -> Result<&mut Enigo> {
+        self.enigo
+            .as_mut()
+            .ok_or_else(|| InputFlowError::Uninitialized.with_message("enigo is not set up"))
     }
 }
 
-#[derive(Debug)]
-pub struct InputFlowNative {
-    enigo: Enigo,
-}
-
 impl Clone for InputFlowNative {
     fn clone(&self) -> Self {
         Self::default()
@@ -53,22 +49,16 @@ impl Loadable for InputFlowNative {
 
 impl Default for InputFlowNative {
     fn default() -> Self {
-        Self {
-            enigo: Enigo::new(&Settings {
-                release_keys_when_dropped: true,
-                ..Default::default()
-            })
-            .expect("setting up enigo"),
-        }
+        Self::new().unwrap_or(Self { enigo: None })
     }
 }
 
-/// VK_LBUTTON	0x01	Left mouse button
-/// VK_RBUTTON	0x02	Right mouse button
-/// VK_CANCEL	0x03	Control-break processing
-/// VK_MBUTTON	0x04	Middle mouse button
-/// VK_XBUTTON1	0x05	X1 mouse button
-/// VK_XBUTTON2	0x06	X2 mouse button
+/// VK_LBUTTON 0x01 Left mouse button
+/// VK_RBUTTON 0x02 Right mouse button
+/// VK_CANCEL 0x03 Control-break processing
+/// VK_MBUTTON 0x04 Middle mouse button
+/// VK_XBUTTON1 0x05 X1 mouse button
+/// VK_XBUTTON2 0x06 X2 mouse button
 /// https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
 /// Buttons align with windows VKEY spec, scroll is appended
 /// TODO: move this to the library crate so the interface is standadized for all plugins
@@ -114,78 +104,118 @@ fn keycode_to_button(btn: MouseButton) -> Option<Button> {
 impl MouseWriter for InputFlowNative {
     #[doc = r" Sends mouse button press down event"]
     fn send_button_down(&mut self, button: MouseButton) -> Result<()> {
-        if let Some(key) = keycode_to_button(button) {
-            self.enigo
-                .button(key, Direction::Press)
-                .map_err(|_| InputFlowError::SendError)
-        } else {
-            Err(InputFlowError::InvalidKey)
-        }
+        catch_panic(|| {
+            if let Some(key) = keycode_to_button(button) {
+                self.enigo()?
+                    .button(key, Direction::Press)
+                    .map_err(|_| InputFlowError::SendError)
+            } else {
+                Err(InputFlowError::InvalidKey)
+            }
+        })
     }
 
     #[doc = r" Releases a mouse button that was set to down previously"]
     fn send_button_up(&mut self, button: MouseButton) -> Result<()> {
-        if let Some(button) = keycode_to_button(button) {
-            self.enigo
-                .button(button, Direction::Release)
-                .map_err(|_| InputFlowError::SendError)
-        } else {
-            Err(InputFlowError::InvalidKey)
-        }
+        catch_panic(|| {
+            if let Some(button) = keycode_to_button(button) {
+                self.enigo()?
+                    .button(button, Direction::Release)
+                    .map_err(|_| InputFlowError::SendError)
+            } else {
+                Err(InputFlowError::InvalidKey)
+            }
+        })
     }
 
     #[doc = r" Presses a  mouse button and lets it go all in one for when users do not care about specific timings"]
     fn click_button(&mut self, button: MouseButton) -> Result<()> {
-        if let Some(button) = keycode_to_button(button) {
-            self.enigo
-                .button(button, Direction::Click)
-                .map_err(|_| InputFlowError::SendError)
-        } else {
-            Err(InputFlowError::InvalidKey)
-        }
+        catch_panic(|| {
+            if let Some(button) = keycode_to_button(button) {
+                self.enigo()?
+                    .button(button, Direction::Click)
+                    .map_err(|_| InputFlowError::SendError)
+            } else {
+                Err(InputFlowError::InvalidKey)
+            }
+        })
     }
 
     #[doc = r" clears all active pressed  mouse buttons. Useful for cleaning up multiple mouse button presses in one go."]
     #[doc = r" Ensures that mouse writer is set back into a neutral state."]
     fn clear_buttons(&mut self) -> Result<()> {
-        let (held_keys, held_keycodes) = self.enigo.held();
-        for key in held_keys {
-            if self.enigo.key(key, Direction::Release).is_err() {
-                println!("WARN: unable to release {key:?}");
-            };
-        }
-        for keycode in held_keycodes {
-            if self.enigo.raw(keycode, Direction::Release).is_err() {
-                println!("WARN: unable to release {keycode:?}");
-            };
-        }
-        Ok(())
+        catch_panic(|| {
+            let enigo = self.enigo()?;
+            let (held_keys, held_keycodes) = enigo.held();
+            let mut stuck = Vec::new();
+            for key in held_keys {
+                if enigo.key(key, Direction::Release).is_err() {
+                    stuck.push(format!("{key:?}"));
+                };
+            }
+            for keycode in held_keycodes {
+                if enigo.raw(keycode, Direction::Release).is_err() {
+                    stuck.push(format!("{keycode:?}"));
+                };
+            }
+            if !stuck.is_empty() {
+                return Err(InputFlowError::SendError
+                    .with_message(format!("unable to release {}", stuck.join(", "))));
+            }
+            Ok(())
+        })
     }
 
     #[doc = r" Sends a mouse move command to move it x dpi-pixels horizontally, and y vertically"]
     fn mouse_move_relative(&mut self, x: i32, y: i32) -> Result<()> {
-        self.enigo
-            .move_mouse(x, y, enigo::Coordinate::Rel)
-            .map_err(|_| InputFlowError::SendError)
+        catch_panic(|| {
+            self.enigo()?
+                .move_mouse(x, y, enigo::Coordinate::Rel)
+                .map_err(|_| InputFlowError::SendError)
+        })
     }
 }
 
-// ================================================================================================================= 
-// =================================== CGlue Plugin init and Header definitions ====================================
-// ================================================================================================================= 
+impl MouseAbsoluteWriter for InputFlowNative {
+    #[doc = r" Moves the mouse to the normalized position `x`, `y` of the main display"]
+    fn mouse_move_absolute(&mut self, x: f32, y: f32) -> Result<()> {
+        catch_panic(|| {
+            let bounds = self.screen_bounds()?;
+            let x = (x.clamp(0.0, 1.0) * bounds.width.saturating_sub(1) as f32).round() as i32;
+            let y = (y.clamp(0.0, 1.0) * bounds.height.saturating_sub(1) as f32).round() as i32;
+            self.enigo()?
+                .move_mouse(x, y, enigo::Coordinate::Abs)
+                .map_err(|_| InputFlowError::SendError)
+        })
+    }
 
-cglue_impl_group!(InputFlowNative, ControllerFeatures,{MouseWriter}, {MouseWriter} );
-#[allow(improper_ctypes_definitions)] // the linter is being stupid and not noticing the repr(u8)
-extern "C" fn create_plugin(lib: &CArc<cglue::trait_group::c_void>, _args: *const std::ffi::c_char) -> Result<PluginInnerArcBox<'static>> {
-    // type_identity!();
-    Ok(trait_obj!((NativePluginRoot::default(), lib.clone()) as PluginInner))
+    #[doc = r" Returns the size of the main display in pixels"]
+    fn screen_bounds(&self) -> Result<ScreenBounds> {
+        catch_panic(|| {
+            let (width, height) = self
+                .enigo
+                .as_ref()
+                .ok_or_else(|| InputFlowError::Uninitialized.with_message("enigo is not set up"))?
+                .main_display()
+                .map_err(|e| InputFlowError::Uninitialized.with_message(e.to_string()))?;
+            Ok(ScreenBounds {
+                width: width as u32,
+                height: height as u32,
+            })
+        })
+    }
 }
 
-#[no_mangle]
-pub static IF_PLUGIN_HEAD: PluginHeader = PluginHeader {
-    features: FeatureSupport::from_bits_retain(
-        FeatureSupport::WRITE_MOUSE.bits(),
-    ),
-    layout: ROOT_LAYOUT,
-    create: create_plugin,
-};
+// =================================================================================================================
+// =================================== CGlue Plugin init and Header definitions ====================================
+// =================================================================================================================
+
+inputflow::define_plugin! {
+    controller: InputFlowNative,
+    args: NoArgs,
+    arg_help: &[],
+    create: |_| InputFlowNative::new(),
+    traits: { MouseWriter, MouseAbsoluteWriter },
+    name: "native",
+    description: "Controls the mouse of the local computer",
+}
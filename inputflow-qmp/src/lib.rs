@@ -1,12 +1,15 @@
-use std::io::Write;
-use std::net::{TcpStream, SocketAddr};
-use std::str;
-use serde_json::json;
 use inputflow::prelude::*;
 use log::error;
+use serde_json::json;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::str;
 
 mod args;
 
+/// Upper bound of the qemu absolute pointer axis (`INPUT_EVENT_ABS_MAX`)
+const QEMU_ABS_MAX: f32 = 0x7FFF as f32;
+
 /// The plugin that handles the input flow functionality.
 #[derive(Default)]
 struct InputFlowQmp {
@@ -25,35 +28,49 @@ impl Loadable for InputFlowQmp {
 }
 
 impl InputFlowQmp {
-    /// Connect to the specified address and port.
-    fn connect(&mut self, address: &str, port: u32) -> bool {
-        if self.connected {
-            println!("Connection is already open");
-            return true;
+    /// Connects to the qmp socket given in `args` and enables its commands
+    fn new(args: args::Args) -> Result<Self> {
+        let mut qmp = Self::default();
+        if let Err(e) = qmp.connect(args.address.as_str(), args.port) {
+            error!("Failed to connect to qmp at {}:{}", args.address, args.port);
+            return Err(e);
         }
 
-        let socket_address: SocketAddr = format!("{}:{}", address, port)
-            .parse()
-            .expect("Invalid address format");
+        if !qmp.enable_commands() {
+            return Err(InputFlowError::Loading);
+        }
 
-        match TcpStream::connect(socket_address) {
-            Ok(stream) => {
-                self.stream = Some(stream);
-                self.connected = true;
-                println!("Connected to {}", address);
-                true
-            }
-            Err(e) => {
-                println!("Could not connect to socket: {}", e);
-                false
-            }
+        Ok(qmp)
+    }
+
+    /// Connect to the specified address and port.
+    fn connect(&mut self, address: &str, port: u32) -> Result<()> {
+        if self.connected {
+            log::debug!("Connection is already open");
+            return Ok(());
         }
+
+        let socket_address: SocketAddr = format!("{}:{}", address, port).parse().map_err(|e| {
+            InputFlowError::Parameter
+                .with_message(format!("invalid qmp address {address}:{port}: {e}"))
+        })?;
+
+        let stream = TcpStream::connect(socket_address).map_err(|e| {
+            let message = format!("could not connect to qmp at {socket_address}: {e}");
+            InputFlowError::from(e).with_message(message)
+        })?;
+        self.stream = Some(stream);
+        self.connected = true;
+        log::info!("Connected to {}", address);
+        Ok(())
     }
 
     /// Disconnect from the socket.
     fn disconnect(&mut self) {
         if let Some(stream) = self.stream.take() {
-            stream.shutdown(std::net::Shutdown::Both).expect("Shutdown failed");
+            if let Err(e) = stream.shutdown(std::net::Shutdown::Both) {
+                log::warn!("Shutting down the qmp connection failed: {e}");
+            }
         }
         self.connected = false;
     }
@@ -77,14 +94,7 @@ impl InputFlowQmp {
             return Err(InputFlowError::Uninitialized);
         }
 
-        let button_type = match button {
-            MouseButton::Left => "left",
-            MouseButton::Right => "right",
-            MouseButton::Middle => "middle",
-            MouseButton::XButton1 => "side",
-            MouseButton::XButton2 => "extra",
-            _ => return Err(InputFlowError::InvalidKey),
-        };
+        let button_type = qmp_button_name(button).ok_or(InputFlowError::InvalidKey)?;
 
         let message = json!({
             "execute": "input-send-event",
@@ -116,9 +126,9 @@ impl InputFlowQmp {
             }
         });
 
-        self.send_message(message.to_string())
-            .then(|| Ok(()))
-            .unwrap_or_else(|| Err(InputFlowError::SendError))?;
+        if !self.send_message(message.to_string()) {
+            return Err(InputFlowError::SendError);
+        }
         // This is for testing if qmp needs a mouse move event to make clicks happen
         // self.move_mouse(0, 0);
         Ok(())
@@ -155,21 +165,118 @@ impl InputFlowQmp {
         self.send_message(message.to_string())
     }
 
+    /// Move the mouse to the normalized position x and y.
+    /// Requires the vm to have an absolute pointing device such as `usb-tablet` attached.
+    fn move_mouse_absolute(&mut self, x: f32, y: f32) -> bool {
+        if !self.connected {
+            return false;
+        }
+
+        let message = json!({
+            "execute": "input-send-event",
+            "arguments": {
+                "events": [
+                    {
+                        "type": "abs",
+                        "data": {
+                            "axis": "x",
+                            "value": qemu_abs_value(x)
+                        }
+                    },
+                    {
+                        "type": "abs",
+                        "data": {
+                            "axis": "y",
+                            "value": qemu_abs_value(y)
+                        }
+                    }
+                ]
+            }
+        });
+
+        self.send_message(message.to_string())
+    }
+
     /// Send a message to the connected socket.
     fn send_message(&mut self, message: String) -> bool {
         if let Some(stream) = &mut self.stream {
             if let Err(e) = stream.write_all(message.as_bytes()) {
-                println!("Failed to send message: {}", e);
+                error!("Failed to send message: {}", e);
+                set_last_error(format!("failed to send qmp message: {e}"));
                 return false;
             }
             true
         } else {
-            println!("No active connection.");
+            set_last_error("no active qmp connection");
             false
         }
     }
 }
 
+/// Scales a normalized coordinate to the qemu absolute axis range,
+/// rounding to the nearest value like the native backend does for screen pixels
+fn qemu_abs_value(value: f32) -> i32 {
+    (value.clamp(0.0, 1.0) * QEMU_ABS_MAX).round() as i32
+}
+
+/// Maps an inputflow mouse button to the qemu `InputButton` name
+fn qmp_button_name(button: MouseButton) -> Option<&'static str> {
+    Some(match button {
+        MouseButton::Left => "left",
+        MouseButton::Right => "right",
+        MouseButton::Middle => "middle",
+        MouseButton::XButton1 => "side",
+        MouseButton::XButton2 => "extra",
+        MouseButton::ScrollUp => "wheel-up",
+        MouseButton::ScrollDown => "wheel-down",
+        MouseButton::ScrollLeft => "wheel-left",
+        MouseButton::ScrollRight => "wheel-right",
+        _ => return None,
+    })
+}
+
+/// Converts an inputflow event into the qemu `InputEvent` objects performing it
+fn qmp_events_for(event: InputEvent) -> Result<Vec<serde_json::Value>> {
+    let button = |button: MouseButton, down: bool| -> Result<serde_json::Value> {
+        Ok(json!({
+            "type": "btn",
+            "data": {
+                "button": qmp_button_name(button).ok_or(InputFlowError::InvalidKey)?,
+                "down": down
+            }
+        }))
+    };
+
+    Ok(match event {
+        InputEvent::ButtonDown(b) => vec![button(b, true)?],
+        InputEvent::ButtonUp(b) => vec![button(b, false)?],
+        InputEvent::MoveRelative { x, y } => vec![
+            json!({ "type": "rel", "data": { "axis": "x", "value": x } }),
+            json!({ "type": "rel", "data": { "axis": "y", "value": y } }),
+        ],
+        InputEvent::MoveAbsolute { x, y } => vec![
+            json!({ "type": "abs", "data": { "axis": "x", "value": qemu_abs_value(x) } }),
+            json!({ "type": "abs", "data": { "axis": "y", "value": qemu_abs_value(y) } }),
+        ],
+        InputEvent::Wheel(notches) => {
+            // qemu models the wheel as buttons, one press and release per notch
+            let b = if notches > 0 {
+                MouseButton::ScrollUp
+            } else {
+                MouseButton::ScrollDown
+            };
+            let mut events = Vec::new();
+            for _ in 0..notches.unsigned_abs() {
+                events.push(button(b, true)?);
+                events.push(button(b, false)?);
+            }
+            events
+        }
+        // keyboard input is not implemented for qmp yet
+        InputEvent::KeyDown(_) | InputEvent::KeyUp(_) => return Err(InputFlowError::Unsupported),
+    })
+}
+
 // auto disconnect on drop so the user doesn't have to
 impl Drop for InputFlowQmp {
     fn drop(&mut self) {
@@ -179,125 +286,277 @@ impl Drop for InputFlowQmp {
     }
 }
 
-// Define a `PluginInner` to work with the InputFlow plugin framework.
-#[derive(Default)]
-struct NativePluginRoot {
-    controller: InputFlowQmp,
-}
-
-impl<'a> PluginInner<'a> for NativePluginRoot {
-    type BorrowedType = Fwd<&'a mut InputFlowQmp>;
-    type OwnedType = InputFlowQmp;
-    type OwnedTypeMut = InputFlowQmp;
-
-    fn borrow_features(&'a mut self) -> Self::BorrowedType {
-        self.controller.forward_mut()
-    }
-
-    fn into_features(self) -> Self::OwnedType {
-        self.controller
-    }
-
-    fn mut_features(&'a mut self) -> &'a mut Self::OwnedTypeMut {
-        &mut self.controller
-    }
-}
-
 // Implement the mouse functionality for the InputFlow plugin.
 impl MouseWriter for InputFlowQmp {
     /// Sends a mouse button press down event.
     fn send_button_down(&mut self, button: MouseButton) -> Result<()> {
-        if !self.connected {
-            return Err(InputFlowError::Uninitialized);
-        }
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
 
-        return self.send_button(button, true);
+            self.send_button(button, true)
+        })
     }
 
     /// Releases a mouse button that was set to down previously.
     fn send_button_up(&mut self, button: MouseButton) -> Result<()> {
-        if !self.connected {
-            return Err(InputFlowError::Uninitialized);
-        }
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
 
-        return self.send_button(button, false);
+            self.send_button(button, false)
+        })
     }
 
     /// Presses a mouse button and lets it go all in one for when users do not care about specific timings.
     /// WARNING: this blocks for one milisecond and also is very detectable by anti input automation systems.
     /// It is much more recomended to press and release manually with varied delays
     fn click_button(&mut self, button: MouseButton) -> Result<()> {
-        self.send_button_down(button)?;
-        std::thread::sleep(std::time::Duration::from_millis(1));
-        self.send_button_up(button)
+        catch_panic(|| {
+            self.send_button_down(button)?;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            self.send_button_up(button)
+        })
     }
 
     /// Clears all active pressed mouse buttons. Ensures that mouse writer is set back into a neutral state.
     fn clear_buttons(&mut self) -> Result<()> {
-        if !self.connected {
-            return Err(InputFlowError::Uninitialized);
-        }
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
 
-        // Send an "up" event for each button (left, right, middle, etc.)
-        let buttons = vec![
-            MouseButton::Left,
-            MouseButton::Right,
-            MouseButton::Middle,
-            MouseButton::XButton1,
-            MouseButton::XButton2,
-        ];
-
-        for button in buttons {
-            self.send_button_up(button)?;
-        }
+            // Send an "up" event for each button (left, right, middle, etc.)
+            let buttons = vec![
+                MouseButton::Left,
+                MouseButton::Right,
+                MouseButton::Middle,
+                MouseButton::XButton1,
+                MouseButton::XButton2,
+            ];
+
+            for button in buttons {
+                self.send_button_up(button)?;
+            }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Sends a mouse move command to move it by `x` dpi-pixels horizontally, and `y` vertically.
     fn mouse_move_relative(&mut self, x: i32, y: i32) -> Result<()> {
-        if !self.connected {
-            return Err(InputFlowError::Uninitialized);
-        }
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
 
-        self.move_mouse(x, y);
-        Ok(())
+            if !self.move_mouse(x, y) {
+                return Err(InputFlowError::SendError);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl MouseAbsoluteWriter for InputFlowQmp {
+    /// Moves the mouse to the normalized position `x`, `y` using qemu absolute pointer events.
+    /// The screen resolution of the vm is not known over qmp, so `screen_bounds` is left unsupported.
+    fn mouse_move_absolute(&mut self, x: f32, y: f32) -> Result<()> {
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
+
+            if !self.move_mouse_absolute(x, y) {
+                return Err(InputFlowError::SendError);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl EventWriter for InputFlowQmp {
+    /// Sends all events in order as a single `input-send-event` message, nothing for an empty batch.
+    fn send_events(&mut self, events: &[InputEvent]) -> Result<()> {
+        catch_panic(|| {
+            if !self.connected {
+                return Err(InputFlowError::Uninitialized);
+            }
+            if events.is_empty() {
+                return Ok(());
+            }
+
+            let mut qmp_events = Vec::new();
+            for event in events {
+                qmp_events.extend(qmp_events_for(*event)?);
+            }
+
+            let message = json!({
+                "execute": "input-send-event",
+                "arguments": {
+                    "events": qmp_events
+                }
+            });
+
+            if !self.send_message(message.to_string()) {
+                return Err(InputFlowError::SendError);
+            }
+            Ok(())
+        })
     }
 }
 
 // Plugin initialization and interface.
-cglue_impl_group!(InputFlowQmp, ControllerFeatures, {MouseWriter}, {MouseWriter});
+inputflow::define_plugin! {
+    controller: InputFlowQmp,
+    args: args::Args,
+    arg_help: args::ARG_HELP,
+    create: InputFlowQmp::new,
+    traits: { MouseWriter, MouseAbsoluteWriter, EventWriter },
+    name: "qmp",
+    description: "Sends mouse input to a QEMU virtual machine over the QMP protocol",
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Connects a plugin to a local socket standing in for qemu, past the capabilities message
+    fn connect() -> (InputFlowQmp, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let qmp = InputFlowQmp::new(args::Args {
+            address: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port().into(),
+        })
+        .unwrap();
+
+        let (mut qemu, _) = listener.accept().unwrap();
+        qemu.set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        assert_eq!(
+            received(&mut qemu),
+            [json!({ "execute": "qmp_capabilities" })]
+        );
+        (qmp, qemu)
+    }
+
+    /// Every message sent since the last call, empty if there was none
+    fn received(qemu: &mut TcpStream) -> Vec<serde_json::Value> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 1024];
+        while let Ok(n @ 1..) = qemu.read(&mut buf) {
+            data.extend_from_slice(&buf[..n]);
+        }
+        serde_json::Deserializer::from_slice(&data)
+            .into_iter()
+            .collect::<serde_json::Result<_>>()
+            .unwrap()
+    }
 
-#[allow(improper_ctypes_definitions)]
-extern "C" fn create_plugin(lib: &CArc<cglue::trait_group::c_void>, args: *const std::ffi::c_char) -> Result<PluginInnerArcBox<'static>> {
-    env_logger::builder()
-    // .filter_level(log::LevelFilter::Info)
-    .init();
+    #[test]
+    fn button_events() {
+        assert_eq!(
+            qmp_events_for(InputEvent::ButtonDown(MouseButton::XButton1)).unwrap(),
+            [json!({ "type": "btn", "data": { "button": "side", "down": true } })]
+        );
+        assert_eq!(
+            qmp_events_for(InputEvent::ButtonUp(MouseButton::Left)).unwrap(),
+            [json!({ "type": "btn", "data": { "button": "left", "down": false } })]
+        );
+        assert_eq!(
+            qmp_events_for(InputEvent::Wheel(-2)).unwrap(),
+            [
+                json!({ "type": "btn", "data": { "button": "wheel-down", "down": true } }),
+                json!({ "type": "btn", "data": { "button": "wheel-down", "down": false } }),
+                json!({ "type": "btn", "data": { "button": "wheel-down", "down": true } }),
+                json!({ "type": "btn", "data": { "button": "wheel-down", "down": false } }),
+            ]
+        );
+        assert!(qmp_events_for(InputEvent::Wheel(0)).unwrap().is_empty());
+    }
 
-    let args = args::parse_args(args).map_err(|e| {
-        log::error!("Invalid parameters were passed to inputflow_qmp: {e:?}.");
-        InputFlowError::Parameter
-    })?;
+    #[test]
+    fn move_events() {
+        assert_eq!(
+            qmp_events_for(InputEvent::MoveRelative { x: -5, y: 12 }).unwrap(),
+            [
+                json!({ "type": "rel", "data": { "axis": "x", "value": -5 } }),
+                json!({ "type": "rel", "data": { "axis": "y", "value": 12 } }),
+            ]
+        );
+        // clamped to the screen and rounded to the nearest axis value
+        assert_eq!(
+            qmp_events_for(InputEvent::MoveAbsolute { x: 0.5, y: 1.5 }).unwrap(),
+            [
+                json!({ "type": "abs", "data": { "axis": "x", "value": 16384 } }),
+                json!({ "type": "abs", "data": { "axis": "y", "value": 0x7FFF } }),
+            ]
+        );
+        assert_eq!(
+            qmp_events_for(InputEvent::MoveAbsolute { x: -1.0, y: 0.0 }).unwrap(),
+            [
+                json!({ "type": "abs", "data": { "axis": "x", "value": 0 } }),
+                json!({ "type": "abs", "data": { "axis": "y", "value": 0 } }),
+            ]
+        );
+    }
 
+    #[test]
+    fn key_events_are_unsupported() {
+        assert_eq!(
+            qmp_events_for(InputEvent::KeyDown(KeyboardKey::A)),
+            Err(InputFlowError::Unsupported)
+        );
+        assert_eq!(
+            qmp_events_for(InputEvent::KeyUp(KeyboardKey::A)),
+            Err(InputFlowError::Unsupported)
+        );
+    }
 
-    let mut new_plugin = NativePluginRoot::default();
-    if !new_plugin.controller.connect(args.address.as_str(), args.port) {
-        error!("Failed to connect to qmp at {}:{}", args.address, args.port);
-        return Err(InputFlowError::Loading);
+    #[test]
+    fn batches_are_one_message() {
+        let (mut qmp, mut qemu) = connect();
+
+        qmp.send_events(&[
+            InputEvent::ButtonDown(MouseButton::Left),
+            InputEvent::MoveRelative { x: 1, y: 2 },
+        ])
+        .unwrap();
+        assert_eq!(
+            received(&mut qemu),
+            [json!({
+                "execute": "input-send-event",
+                "arguments": {
+                    "events": [
+                        { "type": "btn", "data": { "button": "left", "down": true } },
+                        { "type": "rel", "data": { "axis": "x", "value": 1 } },
+                        { "type": "rel", "data": { "axis": "y", "value": 2 } },
+                    ]
+                }
+            })]
+        );
+
+        // a batch with an unsupported event sends nothing
+        assert_eq!(
+            qmp.send_events(&[
+                InputEvent::ButtonUp(MouseButton::Left),
+                InputEvent::KeyDown(KeyboardKey::A),
+            ]),
+            Err(InputFlowError::Unsupported)
+        );
+        assert!(received(&mut qemu).is_empty());
     }
 
-    if !new_plugin.controller.enable_commands() {
-        return Err(InputFlowError::Loading);
+    #[test]
+    fn empty_batches_send_nothing() {
+        let (mut qmp, mut qemu) = connect();
+
+        qmp.send_events(&[]).unwrap();
+        assert!(received(&mut qemu).is_empty());
     }
-    
-    Ok(trait_obj!((new_plugin, lib.clone()) as PluginInner))
 }
-
-#[no_mangle]
-pub static IF_PLUGIN_HEAD: PluginHeader = PluginHeader {
-    features: FeatureSupport::from_bits_retain(
-        FeatureSupport::WRITE_MOUSE.bits(),
-    ),
-    layout: ROOT_LAYOUT,
-    create: create_plugin,
-};
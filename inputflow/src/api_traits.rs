@@ -1,6 +1,14 @@
-use crate::{error::Result, key_types::{KeyboardKey,MouseButton}};
+// cglue generates group constructors that take one vtable argument per optional trait
+#![allow(clippy::too_many_arguments)]
+
+use crate::{
+    error::{InputFlowError, Result},
+    events::InputEvent,
+    key_types::{KeyboardKey, MouseButton},
+};
 pub use abi_stable::type_layout::TypeLayout;
-use cglue::prelude::v1::*;
+use abi_stable::StableAbi;
+use cglue::prelude::v1::{trait_group::c_void, *};
 
 #[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)] //
 /// Main interface for loadable plugins
@@ -13,9 +21,13 @@ pub trait Loadable {
     fn name(&self) -> abi_stable::std_types::RString;
     /// U8 bitflags of capabilities. Sadly I have not yet figured out how to get the bitflags crate to play nice with abi_stable so this is base type for now
     fn capabilities(&self) -> u8;
+    /// Returns and clears the message describing the last error returned on this thread, empty if there is none
+    fn last_error(&self) -> abi_stable::std_types::RString {
+        crate::error::take_last_error().unwrap_or_default().into()
+    }
 }
 
-cglue_trait_group!(ControllerFeatures, { Loadable }, { KeyboardWriter, MouseWriter, Clone });
+cglue_trait_group!(ControllerFeatures, { Loadable }, { KeyboardWriter, KeyboardReader, MouseWriter, MouseAbsoluteWriter, SmoothMouseWriter, MouseReader, InputInterceptor, EventWriter, ConnectionMonitor, Clone });
 
 /// Provides ability to send keyboard input to a device (local or external).
 #[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
@@ -35,11 +47,13 @@ pub trait KeyboardWriter: Send {
     fn clear_keys(&mut self) -> Result<()>;
 }
 
-// TODO Later on. For now, memflow handles this.
-// #[cfg_attr(feature = "plugins", cglue_trait)]
-// pub trait KeyboardReader {
-//
-// }
+/// Provides ability to read the current keyboard state of a device (local or external).
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait KeyboardReader: Send {
+    /// Returns true if the key is currently held down
+    fn is_key_down(&mut self, key: KeyboardKey) -> Result<bool>;
+}
 
 /// Provides ability to send mouse button input to a device (local or external).
 /// Also allows mouse movement input
@@ -65,8 +79,192 @@ pub trait MouseWriter: Send {
     fn mouse_move_relative(&mut self, x: i32, y: i32) -> Result<()>;
 }
 
-// TODO Later on. For now, memflow handles this.
-// #[cfg_attr(feature = "plugins", cglue_trait)]
-// pub trait MouseReader {
-//
-// }
+/// Size of the screen an absolute mouse writer moves on, in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi)]
+pub struct ScreenBounds {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Provides ability to place the mouse at an absolute position.
+///
+/// Coordinates are normalized: `(0.0, 0.0)` is the top left and `(1.0, 1.0)` the bottom right corner
+/// of the (primary) screen. This way backends that do not know the target resolution, such as qemu, can still support it.
+/// Values outside of that range are clamped.
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait MouseAbsoluteWriter: Send {
+    /// Moves the mouse to the normalized position `x`, `y`
+    fn mouse_move_absolute(&mut self, x: f32, y: f32) -> Result<()>;
+
+    /// Returns the size of the screen in pixels if the backend knows it.
+    /// Use this to convert pixel positions into the normalized coordinates of [`MouseAbsoluteWriter::mouse_move_absolute`].
+    fn screen_bounds(&self) -> Result<ScreenBounds> {
+        Err(InputFlowError::Unsupported)
+    }
+}
+
+/// Control points of a cubic bezier curve, relative to the position the move starts at.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi)]
+pub struct BezierControls {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+/// Provides mouse moves that the device spreads out over time itself.
+///
+/// Backends implement this when the device can smooth moves on its own, e.g. KMBox firmware.
+/// Hosts do not need to check for it: `ControllerFeatures::move_relative_over` falls back to
+/// interpolating on the host through [`MouseWriter`] for plugins that do not implement it.
+/// Distances use the same units as [`MouseWriter::mouse_move_relative`].
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait SmoothMouseWriter: Send {
+    /// Moves the mouse by `x`, `y` in a straight line over `duration_ms` milliseconds
+    fn move_relative_over(&mut self, x: i32, y: i32, duration_ms: u32) -> Result<()>;
+
+    /// Moves the mouse by `x`, `y` along a cubic bezier curve bent by `controls` over `duration_ms` milliseconds
+    fn move_bezier_over(
+        &mut self,
+        x: i32,
+        y: i32,
+        duration_ms: u32,
+        controls: BezierControls,
+    ) -> Result<()>;
+}
+
+/// Position of the mouse cursor on the screen, in pixels.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi)]
+pub struct MousePosition {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Provides ability to read the current mouse state of a device (local or external).
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait MouseReader: Send {
+    /// Returns true if the mouse button is currently held down
+    fn is_button_down(&mut self, button: MouseButton) -> Result<bool>;
+
+    /// Returns the current cursor position if the backend is able to know it.
+    fn mouse_position(&mut self) -> Result<MousePosition> {
+        Err(InputFlowError::Unsupported)
+    }
+}
+
+/// What an [`InterceptCallback`] does with an intercepted physical input
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, StableAbi)]
+pub enum InterceptAction {
+    /// Send the input on to the target unchanged
+    Pass,
+    /// Keep the input from reaching the target
+    Drop,
+    /// Send this event to the target instead
+    Replace(InputEvent),
+}
+
+/// Called for every intercepted physical input, oldest first, and decides what reaches the target.
+///
+/// Works like cglue's `OpaqueCallback`, which can only return `bool`,
+/// and is created the same way: `(&mut |event| InterceptAction::Pass).into()`.
+#[repr(C)]
+#[derive(StableAbi)]
+pub struct InterceptCallback<'a> {
+    context: &'a mut c_void,
+    func: extern "C" fn(&mut c_void, InputEvent) -> InterceptAction,
+}
+
+impl InterceptCallback<'_> {
+    pub fn call(&mut self, event: InputEvent) -> InterceptAction {
+        (self.func)(self.context, event)
+    }
+}
+
+impl<'a, F: FnMut(InputEvent) -> InterceptAction> From<&'a mut F> for InterceptCallback<'a> {
+    fn from(func: &'a mut F) -> Self {
+        extern "C" fn call<F: FnMut(InputEvent) -> InterceptAction>(
+            context: &mut c_void,
+            event: InputEvent,
+        ) -> InterceptAction {
+            // safety: the context was created from a `&mut F` below
+            let func = unsafe { &mut *(context as *mut c_void).cast::<F>() };
+            func(event)
+        }
+
+        Self {
+            // safety: only ever turned back into `&mut F` by `call::<F>`
+            context: unsafe { &mut *(func as *mut F).cast::<c_void>() },
+            func: call::<F>,
+        }
+    }
+}
+
+/// Provides ability to intercept the physical input of a device before it reaches the target.
+///
+/// Intercepted buttons and keys are blocked at the device. Every physical press and release of them
+/// is handed to the callback in the order it happened, which passes, drops or rewrites it.
+/// Inputs sent through the writer traits are not affected, so automation can hold a button
+/// while the physical one is blocked.
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait InputInterceptor: Send {
+    /// Starts or stops intercepting a physical mouse button
+    fn intercept_button(&mut self, button: MouseButton, enabled: bool) -> Result<()>;
+
+    /// Starts or stops intercepting a physical keyboard key
+    fn intercept_key(&mut self, key: KeyboardKey, enabled: bool) -> Result<()>;
+
+    /// Stops all interception so physical input reaches the target unfiltered again.
+    fn clear_intercepts(&mut self) -> Result<()>;
+
+    /// Calls `callback` once for every press and release of an intercepted input since the last poll
+    /// and sends what it returns to the target.
+    fn poll_intercepted(&mut self, callback: InterceptCallback) -> Result<()>;
+}
+
+/// Provides ability to send many input events in one go.
+///
+/// Backends implement this when they can batch events natively, e.g. into a single message or write.
+/// Hosts do not need to check for it: `ControllerFeatures::send_events` falls back to the
+/// individual writer traits for plugins that do not implement it.
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait EventWriter: Send {
+    /// Sends all events in order
+    fn send_events(&mut self, events: &[InputEvent]) -> Result<()>;
+}
+
+/// State of the link between a plugin and its device
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StableAbi)]
+pub enum ConnectionState {
+    Connected,
+    /// The device stopped responding, the plugin keeps trying to reconnect
+    Disconnected,
+}
+
+/// Called for every connection state change, oldest first.
+/// Return `false` to stop receiving the remaining changes of this poll.
+pub type ConnectionCallback<'a> = OpaqueCallback<'a, ConnectionState>;
+
+/// Provides the connection state of plugins that talk to an external device which can go away.
+///
+/// Plugins reconnect on their own, this lets hosts follow along and e.g. pause automation while the
+/// device is gone.
+#[cfg_attr(feature = "plugins", cglue_trait, cglue_forward)]
+#[int_result]
+pub trait ConnectionMonitor: Send {
+    /// Current state of the link to the device
+    fn connection_state(&self) -> ConnectionState;
+
+    /// Calls `callback` once for every state change since the last poll.
+    /// Also gives a disconnected device the chance to reconnect without any input being sent.
+    fn poll_connection_changes(&mut self, callback: ConnectionCallback) -> Result<()>;
+}
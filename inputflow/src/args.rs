@@ -0,0 +1,673 @@
+//! Parsing of the argument string passed to a plugin's `create`.
+//!
+//! Two syntaxes are accepted:
+//!
+//! - RON named arguments: `(com_port: "/dev/ttyUSB1", baud_rate: 9600)`
+//! - memflow style key value pairs: `com_port=/dev/ttyUSB1,baud_rate=9600`
+//!
+//! Values in the key value syntax are converted to the type the plugin declares for the key,
+//! strings containing `,` can be quoted: `device_name="a, b"`. Integers may be written in hex
+//! with a `0x` prefix.
+//!
+//! Arguments of type `Option<T>` may always be left out and are `None` then. They take a plain
+//! `T` value in both syntaxes, RON also accepts `Some(..)` and `None`, and an empty `key=` is `None`.
+
+use std::ffi::{c_char, CStr};
+
+use serde::de::DeserializeOwned;
+
+use crate::{error::InputFlowError, headers::ArgDescriptor};
+
+/// Describes an argument a plugin accepts, see [`ArgDescriptor`]
+pub trait ArgSpec {
+    fn name(&self) -> &str;
+    /// `bool`, `String`, `f32`/`f64`, an integer type such as `u32`, or `Option` of one of them
+    fn type_name(&self) -> &str;
+    /// Whether the argument may be left out
+    fn has_default(&self) -> bool;
+}
+
+impl ArgSpec for ArgDescriptor {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn type_name(&self) -> &str {
+        self.type_name.as_str()
+    }
+
+    fn has_default(&self) -> bool {
+        !self.default.is_empty()
+    }
+}
+
+/// Why an argument string was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgsError {
+    /// The string is neither valid RON nor `key=value` pairs
+    Syntax(String),
+    UnknownKey {
+        key: String,
+        known: Vec<String>,
+    },
+    Type {
+        key: String,
+        expected: String,
+        found: String,
+    },
+    Missing(String),
+    /// The values passed validation but the plugin could not use them
+    Invalid(String),
+}
+
+impl std::fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ArgsError::Syntax(e) => write!(f, "invalid argument syntax: {e}"),
+            ArgsError::UnknownKey { key, known } if known.is_empty() => {
+                write!(f, "unknown argument `{key}`, no arguments are accepted")
+            }
+            ArgsError::UnknownKey { key, known } => write!(
+                f,
+                "unknown argument `{key}`, expected one of `{}`",
+                known.join("`, `")
+            ),
+            ArgsError::Type {
+                key,
+                expected,
+                found,
+            } => write!(f, "argument `{key}` expects a {expected}, found {found}"),
+            ArgsError::Missing(key) => write!(f, "missing required argument `{key}`"),
+            ArgsError::Invalid(e) => write!(f, "invalid arguments: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+impl From<ArgsError> for InputFlowError {
+    fn from(e: ArgsError) -> Self {
+        InputFlowError::Parameter.with_message(e.to_string())
+    }
+}
+
+/// Parses the default of an argument written in argument syntax, used by [`crate::define_args!`]
+#[doc(hidden)]
+pub fn default_value<T: DeserializeOwned>(name: &str, default: &str) -> T {
+    ron::from_str(default)
+        .unwrap_or_else(|e| panic!("default {default} of argument `{name}` is invalid: {e}"))
+}
+
+/// Argument type of plugins that do not take any arguments
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+pub struct NoArgs {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArgValue {
+    /// Typed value from RON or a quoted string
+    Value(ron::Value),
+    /// Unquoted text from `key=value`, converted once the expected type is known
+    Text(String),
+}
+
+/// Parsed plugin arguments
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectorArgs {
+    entries: Vec<(String, ArgValue)>,
+}
+
+impl ConnectorArgs {
+    /// Parses arguments in either RON or `key=value` syntax
+    pub fn parse(args: &str) -> Result<Self, ArgsError> {
+        let args = args.trim();
+        if args.is_empty() {
+            Ok(Self::default())
+        } else if args.starts_with('(') || args.starts_with('{') {
+            Self::parse_ron(args)
+        } else {
+            Self::parse_key_values(args)
+        }
+    }
+
+    /// Parses the argument string handed to a plugin's `create`.
+    ///
+    /// The string is only borrowed, it stays owned by the host.
+    ///
+    /// # Safety
+    ///
+    /// `args` has to be null or point to a nul terminated string that outlives this call.
+    pub unsafe fn from_raw(args: *const c_char) -> Result<Self, ArgsError> {
+        if args.is_null() {
+            return Ok(Self::default());
+        }
+        let args = CStr::from_ptr(args)
+            .to_str()
+            .map_err(|e| ArgsError::Syntax(e.to_string()))?;
+        Self::parse(args)
+    }
+
+    fn parse_ron(args: &str) -> Result<Self, ArgsError> {
+        let map = match ron::from_str::<ron::Value>(args)
+            .map_err(|e| ArgsError::Syntax(e.to_string()))?
+        {
+            ron::Value::Map(map) => map,
+            other => {
+                return Err(ArgsError::Syntax(format!(
+                    "expected named arguments, found {other:?}"
+                )))
+            }
+        };
+
+        let mut parsed = Self::default();
+        for (key, value) in map.into_iter() {
+            let ron::Value::String(key) = key else {
+                return Err(ArgsError::Syntax(format!(
+                    "argument names must be identifiers, found {key:?}"
+                )));
+            };
+            parsed.insert(key, ArgValue::Value(value))?;
+        }
+        Ok(parsed)
+    }
+
+    fn parse_key_values(args: &str) -> Result<Self, ArgsError> {
+        let mut parsed = Self::default();
+        for pair in split_unquoted(args, ',') {
+            let pair = pair.trim();
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| ArgsError::Syntax(format!("expected key=value, found `{pair}`")))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() {
+                return Err(ArgsError::Syntax(format!(
+                    "missing argument name in `{pair}`"
+                )));
+            }
+
+            let value = if value.starts_with('"') {
+                let text = ron::from_str::<String>(value)
+                    .map_err(|e| ArgsError::Syntax(format!("argument `{key}`: {e}")))?;
+                ArgValue::Value(ron::Value::String(text))
+            } else {
+                ArgValue::Text(value.to_string())
+            };
+            parsed.insert(key.to_string(), value)?;
+        }
+        Ok(parsed)
+    }
+
+    fn insert(&mut self, key: String, value: ArgValue) -> Result<(), ArgsError> {
+        if self.entries.iter().any(|(k, _)| *k == key) {
+            return Err(ArgsError::Syntax(format!(
+                "argument `{key}` is given twice"
+            )));
+        }
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Names of the given arguments, in the order they were written
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// Checks the arguments against `spec` without converting them
+    pub fn validate<S: ArgSpec>(&self, spec: &[S]) -> Result<(), ArgsError> {
+        self.typed(spec).map(|_| ())
+    }
+
+    /// Validates the arguments against `spec` and deserializes them into the plugin's argument struct.
+    ///
+    /// Left out arguments are filled in by the serde defaults of `T`.
+    pub fn parse_into<T: DeserializeOwned, S: ArgSpec>(&self, spec: &[S]) -> Result<T, ArgsError> {
+        ron::Value::Map(self.typed(spec)?)
+            .into_rust()
+            .map_err(|e| ArgsError::Invalid(e.to_string()))
+    }
+
+    fn typed<S: ArgSpec>(&self, spec: &[S]) -> Result<ron::Map, ArgsError> {
+        let mut map = ron::Map::new();
+        for (key, value) in &self.entries {
+            let arg =
+                spec.iter()
+                    .find(|a| a.name() == key)
+                    .ok_or_else(|| ArgsError::UnknownKey {
+                        key: key.clone(),
+                        known: spec.iter().map(|a| a.name().to_string()).collect(),
+                    })?;
+            map.insert(
+                ron::Value::String(key.clone()),
+                typed_value(key, value, arg.type_name())?,
+            );
+        }
+
+        if let Some(missing) = spec.iter().find(|a| {
+            !a.has_default()
+                && option_type(a.type_name()).is_none()
+                && !self.keys().any(|k| k == a.name())
+        }) {
+            return Err(ArgsError::Missing(missing.name().to_string()));
+        }
+
+        Ok(map)
+    }
+}
+
+impl std::str::FromStr for ConnectorArgs {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Splits `s` on `sep`, ignoring separators inside double quotes
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Returns true if the integer fits the declared integer type
+fn int_fits(value: i64, type_name: &str) -> bool {
+    match type_name {
+        "u8" => u8::try_from(value).is_ok(),
+        "u16" => u16::try_from(value).is_ok(),
+        "u32" => u32::try_from(value).is_ok(),
+        "u64" | "usize" => value >= 0,
+        "i8" => i8::try_from(value).is_ok(),
+        "i16" => i16::try_from(value).is_ok(),
+        "i32" => i32::try_from(value).is_ok(),
+        _ => true,
+    }
+}
+
+/// Returns `T` of an `Option<T>` type name
+fn option_type(type_name: &str) -> Option<&str> {
+    type_name
+        .strip_prefix("Option")?
+        .trim_start()
+        .strip_prefix('<')?
+        .strip_suffix('>')
+        .map(str::trim)
+}
+
+/// Parses a decimal integer, or a hex integer with a `0x` prefix
+fn parse_int(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Converts a parsed value to the type declared for `key`
+fn typed_value(key: &str, value: &ArgValue, type_name: &str) -> Result<ron::Value, ArgsError> {
+    if let Some(inner) = option_type(type_name) {
+        let some = match value {
+            ArgValue::Value(ron::Value::Option(None)) => return Ok(ron::Value::Option(None)),
+            ArgValue::Text(text) if text.is_empty() => return Ok(ron::Value::Option(None)),
+            ArgValue::Value(ron::Value::Option(Some(value))) => ArgValue::Value((**value).clone()),
+            value => value.clone(),
+        };
+        return typed_value(key, &some, inner)
+            .map(|value| ron::Value::Option(Some(Box::new(value))));
+    }
+
+    let mismatch = |found: String| ArgsError::Type {
+        key: key.to_string(),
+        expected: type_name.to_string(),
+        found,
+    };
+
+    match value {
+        ArgValue::Value(value) => {
+            let matches = match type_name {
+                "bool" => matches!(value, ron::Value::Bool(_)),
+                "String" => matches!(value, ron::Value::String(_)),
+                "f32" | "f64" => matches!(value, ron::Value::Number(_)),
+                _ => {
+                    matches!(value, ron::Value::Number(ron::Number::Integer(i)) if int_fits(*i, type_name))
+                }
+            };
+            if matches {
+                Ok(value.clone())
+            } else {
+                Err(mismatch(format!("{value:?}")))
+            }
+        }
+        ArgValue::Text(text) => match type_name {
+            "bool" => text.parse().map(ron::Value::Bool).ok(),
+            "String" => Some(ron::Value::String(text.clone())),
+            "f32" | "f64" => text
+                .parse::<f64>()
+                .ok()
+                .map(|f| ron::Value::Number(f.into())),
+            _ => parse_int(text)
+                .filter(|i| int_fits(*i, type_name))
+                .map(|i| ron::Value::Number(ron::Number::Integer(i))),
+        }
+        .ok_or_else(|| mismatch(format!("`{text}`"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &[ArgDescriptor] = &[
+        ArgDescriptor::new("com_port", "String", "", "serial port"),
+        ArgDescriptor::new("baud_rate", "u32", "115200", "baud rate"),
+        ArgDescriptor::new("offset", "i8", "0", "signed value"),
+        ArgDescriptor::new("auto_select", "bool", "true", "auto select"),
+        ArgDescriptor::new("device_name", "String", "\"kmbox\"", "device name"),
+    ];
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Args {
+        com_port: String,
+        #[serde(default = "default_baud_rate")]
+        baud_rate: u32,
+        #[serde(default)]
+        offset: i8,
+        #[serde(default = "default_auto_select")]
+        auto_select: bool,
+        #[serde(default)]
+        device_name: String,
+    }
+
+    fn default_baud_rate() -> u32 {
+        115_200
+    }
+
+    fn default_auto_select() -> bool {
+        true
+    }
+
+    fn parse(args: &str) -> Result<Args, ArgsError> {
+        ConnectorArgs::parse(args)?.parse_into(SPEC)
+    }
+
+    #[test]
+    fn both_syntaxes() {
+        let expected = Args {
+            com_port: "/dev/ttyUSB1".to_string(),
+            baud_rate: 9600,
+            offset: -3,
+            auto_select: false,
+            device_name: String::new(),
+        };
+        assert_eq!(
+            parse(r#"(com_port: "/dev/ttyUSB1", baud_rate: 9600, offset: -3, auto_select: false)"#),
+            Ok(expected)
+        );
+        assert_eq!(
+            parse("com_port=/dev/ttyUSB1, baud_rate=9600,offset=-3,auto_select=false"),
+            parse(r#"(com_port: "/dev/ttyUSB1", baud_rate: 9600, offset: -3, auto_select: false)"#)
+        );
+    }
+
+    #[test]
+    fn defaults_fill_left_out_arguments() {
+        let args = parse("com_port=COM6").unwrap();
+        assert_eq!(args.baud_rate, 115_200);
+        assert!(args.auto_select);
+
+        assert!(ConnectorArgs::parse("").unwrap().is_empty());
+        assert!(ConnectorArgs::parse("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quoted_values_keep_commas() {
+        let args = parse(r#"device_name="a, b",com_port="x=\"y\"""#).unwrap();
+        assert_eq!(args.device_name, "a, b");
+        assert_eq!(args.com_port, "x=\"y\"");
+        assert_eq!(
+            ConnectorArgs::parse("com_port=a,b").unwrap_err(),
+            ArgsError::Syntax("expected key=value, found `b`".to_string())
+        );
+    }
+
+    #[test]
+    fn integers_are_range_checked() {
+        assert_eq!(parse("com_port=a,offset=-128").unwrap().offset, -128);
+        for (args, key, expected, found) in [
+            ("com_port=a,offset=128", "offset", "i8", "`128`"),
+            ("com_port=a,baud_rate=-1", "baud_rate", "u32", "`-1`"),
+            (
+                "com_port=a,baud_rate=4294967296",
+                "baud_rate",
+                "u32",
+                "`4294967296`",
+            ),
+            ("com_port=a,baud_rate=fast", "baud_rate", "u32", "`fast`"),
+            (
+                "(com_port: \"a\", offset: -129)",
+                "offset",
+                "i8",
+                "Number(Integer(-129))",
+            ),
+        ] {
+            assert_eq!(
+                parse(args),
+                Err(ArgsError::Type {
+                    key: key.to_string(),
+                    expected: expected.to_string(),
+                    found: found.to_string(),
+                }),
+                "{args}"
+            );
+        }
+        assert!(matches!(
+            parse("com_port=a,auto_select=yes"),
+            Err(ArgsError::Type { .. })
+        ));
+        assert!(matches!(
+            parse("(com_port: 5)"),
+            Err(ArgsError::Type { .. })
+        ));
+    }
+
+    #[test]
+    fn optional_arguments() {
+        const SPEC: &[ArgDescriptor] = &[
+            ArgDescriptor::new("vid", "Option<u16>", "", "usb vendor id"),
+            ArgDescriptor::new("serial", "Option < String >", "None", "serial number"),
+        ];
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Optional {
+            vid: Option<u16>,
+            serial: Option<String>,
+        }
+
+        let parse = |args: &str| -> Result<Optional, ArgsError> {
+            ConnectorArgs::parse(args)?.parse_into(SPEC)
+        };
+        let optional = |vid: Option<u16>, serial: Option<&str>| Optional {
+            vid,
+            serial: serial.map(Into::into),
+        };
+
+        assert_eq!(parse(""), Ok(optional(None, None)));
+        assert_eq!(parse("vid=0x1a86"), Ok(optional(Some(0x1a86), None)));
+        assert_eq!(
+            parse("vid=6790,serial=A1"),
+            Ok(optional(Some(6790), Some("A1")))
+        );
+        assert_eq!(parse("vid=,serial="), Ok(optional(None, None)));
+        assert_eq!(parse("serial=\"\""), Ok(optional(None, Some(""))));
+        assert_eq!(
+            parse(r#"(vid: 0x1a86, serial: Some("A1"))"#),
+            Ok(optional(Some(0x1a86), Some("A1")))
+        );
+        assert_eq!(
+            parse("(vid: Some(1), serial: None)"),
+            Ok(optional(Some(1), None))
+        );
+
+        for (args, found) in [
+            ("vid=0x12345", "`0x12345`"),
+            ("vid=1a86", "`1a86`"),
+            ("(vid: \"1\")", "String(\"1\")"),
+            ("(vid: Some(-1))", "Number(Integer(-1))"),
+        ] {
+            assert_eq!(
+                parse(args),
+                Err(ArgsError::Type {
+                    key: "vid".to_string(),
+                    expected: "u16".to_string(),
+                    found: found.to_string(),
+                }),
+                "{args}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let error = parse("com_port=a,baud=9600").unwrap_err();
+        assert_eq!(
+            error,
+            ArgsError::UnknownKey {
+                key: "baud".to_string(),
+                known: SPEC.iter().map(|a| a.name().to_string()).collect(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown argument `baud`, expected one of `com_port`, `baud_rate`, `offset`, `auto_select`, `device_name`"
+        );
+        assert_eq!(
+            ConnectorArgs::parse("x=1")
+                .unwrap()
+                .validate::<ArgDescriptor>(&[])
+                .unwrap_err()
+                .to_string(),
+            "unknown argument `x`, no arguments are accepted"
+        );
+    }
+
+    #[test]
+    fn missing_required_arguments() {
+        assert_eq!(
+            parse("baud_rate=9600"),
+            Err(ArgsError::Missing("com_port".to_string()))
+        );
+        assert_eq!(parse(""), Err(ArgsError::Missing("com_port".to_string())));
+    }
+
+    #[test]
+    fn invalid_syntax() {
+        for args in [
+            "com_port",
+            "=a",
+            "com_port=a,com_port=b",
+            "(com_port: \"a\"",
+            "(\"com_port\": \"a\")",
+            "device_name=\"unterminated",
+        ] {
+            assert!(
+                matches!(ConnectorArgs::parse(args), Err(ArgsError::Syntax(_))),
+                "{args}"
+            );
+        }
+    }
+
+    crate::define_args! {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Defined {
+            /// The serial port,
+            /// e.g. /dev/ttyUSB0
+            com_port: String = "/dev/ttyUSB0",
+            #[cfg(unix)]
+            baud_rate: u32 = 9600,
+            #[cfg(not(unix))]
+            baud_rate: u32 = 115200,
+            /// Picks the n-th device
+            index: i8 = -1,
+            /// Only devices with this serial number
+            serial: Option<String> = None,
+        }
+    }
+
+    #[test]
+    fn defined_args() {
+        assert_eq!(ARG_HELP.len(), 4);
+        let com_port = &ARG_HELP[0];
+        assert_eq!(com_port.name(), "com_port");
+        assert_eq!(com_port.type_name(), "String");
+        assert_eq!(com_port.default.as_str(), "\"/dev/ttyUSB0\"");
+        assert_eq!(
+            com_port.description.as_str(),
+            "The serial port, e.g. /dev/ttyUSB0"
+        );
+        assert_eq!(ARG_HELP[2].default.as_str(), "-1");
+        assert!(ARG_HELP[1].description.is_empty());
+        assert_eq!(ARG_HELP[3].type_name(), "Option<String>");
+        assert_eq!(ARG_HELP[3].default.as_str(), "None");
+
+        let defined: Defined = ConnectorArgs::parse("")
+            .unwrap()
+            .parse_into(ARG_HELP)
+            .unwrap();
+        assert_eq!(defined, Defined::default());
+        assert_eq!(defined.com_port, "/dev/ttyUSB0");
+        assert_eq!(defined.index, -1);
+        #[cfg(unix)]
+        assert_eq!(defined.baud_rate, 9600);
+
+        let defined: Defined = ConnectorArgs::parse("index=2")
+            .unwrap()
+            .parse_into(ARG_HELP)
+            .unwrap();
+        assert_eq!(defined.index, 2);
+        assert_eq!(defined.com_port, "/dev/ttyUSB0");
+        assert_eq!(defined.serial, None);
+
+        let defined: Defined = ConnectorArgs::parse("serial=A1")
+            .unwrap()
+            .parse_into(ARG_HELP)
+            .unwrap();
+        assert_eq!(defined.serial.as_deref(), Some("A1"));
+    }
+
+    #[test]
+    fn from_raw() {
+        let args = c"com_port=/dev/ttyACM0,baud_rate=9600";
+        let parsed = unsafe { ConnectorArgs::from_raw(args.as_ptr()) }.unwrap();
+        assert_eq!(parsed.keys().collect::<Vec<_>>(), ["com_port", "baud_rate"]);
+        assert_eq!(parsed.parse_into::<Args, _>(SPEC).unwrap().baud_rate, 9600);
+
+        assert!(unsafe { ConnectorArgs::from_raw(std::ptr::null()) }
+            .unwrap()
+            .is_empty());
+
+        let invalid_utf8 = c"com_port=\xff";
+        assert!(matches!(
+            unsafe { ConnectorArgs::from_raw(invalid_utf8.as_ptr()) },
+            Err(ArgsError::Syntax(_))
+        ));
+    }
+}
@@ -1,9 +1,14 @@
+use abi_stable::std_types::{ROption, RString};
 use cglue::result::IntError;
+use std::cell::RefCell;
 use std::num::NonZeroI32;
 
-/// Describes possible errors that can occur loading the library
+/// Describes possible errors that can occur loading the library or talking to a device.
+///
+/// Only the code crosses the plugin boundary. Details are passed along separately,
+/// see [`set_last_error`].
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputFlowError {
     Path = 1,
     Loading = 2,
@@ -13,6 +18,26 @@ pub enum InputFlowError {
     SendError = 6,
     Parameter = 7,
     Uninitialized = 8,
+    Unsupported = 9,
+    /// The device did not answer in time
+    Timeout = 10,
+    /// The connection to the device was lost
+    Disconnected = 11,
+    /// An os level io operation failed
+    Io = 12,
+    /// The plugin panicked, the panic message is available as last error
+    Panic = 13,
+    /// A code this version of inputflow does not know, e.g. from a newer plugin
+    Unknown = 255,
+}
+
+impl InputFlowError {
+    /// Records `message` as the last error of this thread and returns the error,
+    /// for use in `map_err` and `Err(...)` expressions.
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        set_last_error(message);
+        self
+    }
 }
 
 impl IntError for InputFlowError {
@@ -29,17 +54,94 @@ impl IntError for InputFlowError {
             5 => Self::InvalidKey,
             6 => Self::SendError,
             7 => Self::Parameter,
-            _ => unreachable!(),
+            8 => Self::Uninitialized,
+            9 => Self::Unsupported,
+            10 => Self::Timeout,
+            11 => Self::Disconnected,
+            12 => Self::Io,
+            13 => Self::Panic,
+            _ => Self::Unknown,
         }
     }
 }
 
 impl std::fmt::Display for InputFlowError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        let description = match self {
+            Self::Path => "plugin not found",
+            Self::Loading => "failed to load",
+            Self::Symbol => "plugin header not found",
+            Self::Abi => "incompatible plugin abi",
+            Self::InvalidKey => "key or button not supported",
+            Self::SendError => "failed to send input",
+            Self::Parameter => "invalid parameter",
+            Self::Uninitialized => "not initialized",
+            Self::Unsupported => "operation not supported",
+            Self::Timeout => "timed out",
+            Self::Disconnected => "device disconnected",
+            Self::Io => "io error",
+            Self::Panic => "plugin panicked",
+            Self::Unknown => "unknown error",
+        };
+        f.write_str(description)
     }
 }
 
 impl std::error::Error for InputFlowError {}
 
+impl From<std::io::Error> for InputFlowError {
+    fn from(e: std::io::Error) -> Self {
+        let kind = match e.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Self::Timeout,
+            std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::NotConnected
+            | std::io::ErrorKind::UnexpectedEof => Self::Disconnected,
+            _ => Self::Io,
+        };
+        kind.with_message(e.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, InputFlowError>;
+
+/// Runs `f` and turns a panic into [`InputFlowError::Panic`], keeping the panic message as last error.
+///
+/// A panic must not unwind out of an `extern "C"` function, so plugins run `create` and
+/// every trait method through this.
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+        log::error!("plugin panicked: {message}");
+        Err(InputFlowError::Panic.with_message(message))
+    })
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Stores a human readable description of the error the current call is about to return.
+///
+/// Every plugin has its own copy of this storage. The host reads it through
+/// [`Loadable::last_error`](crate::api_traits::Loadable::last_error), or through
+/// [`PluginHeader::last_error`](crate::headers::PluginHeader::last_error) when `create` failed.
+pub fn set_last_error(message: impl Into<String>) {
+    let message = message.into();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Returns and clears the last error message stored on this thread
+pub fn take_last_error() -> Option<String> {
+    LAST_ERROR.with(|last| last.borrow_mut().take())
+}
+
+/// [`take_last_error`] for the plugin header, plugins export this as `last_error`
+pub extern "C" fn plugin_last_error() -> ROption<RString> {
+    take_last_error().map(RString::from).into()
+}
@@ -0,0 +1,83 @@
+//! Input events shared between the batch and interception apis.
+
+use abi_stable::StableAbi;
+use cglue::prelude::v1::{trait_group::c_void, *};
+
+use crate::{
+    api_traits::*,
+    error::{InputFlowError, Result},
+    key_types::{KeyboardKey, MouseButton},
+};
+
+/// A single input action that can be sent in a batch through [`EventWriter`]
+/// or is reported by an [`InputInterceptor`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, StableAbi)]
+pub enum InputEvent {
+    KeyDown(KeyboardKey),
+    KeyUp(KeyboardKey),
+    ButtonDown(MouseButton),
+    ButtonUp(MouseButton),
+    /// Same units as [`MouseWriter::mouse_move_relative`]
+    MoveRelative {
+        x: i32,
+        y: i32,
+    },
+    /// Same normalized coordinates as [`MouseAbsoluteWriter::mouse_move_absolute`]
+    MoveAbsolute {
+        x: f32,
+        y: f32,
+    },
+    /// Scrolls the vertical wheel by this many notches. Positive values scroll up (away from the user).
+    Wheel(i32),
+}
+
+impl<'a> ControllerFeatures<'a, CBox<'a, c_void>, CArc<c_void>> {
+    /// Sends all events in order.
+    ///
+    /// Uses the native batch support of the plugin if it implements [`EventWriter`],
+    /// otherwise every event is sent on its own through the matching writer trait.
+    /// Fails with [`InputFlowError::Unsupported`] on the first event the plugin has no writer for.
+    pub fn send_events(&mut self, events: &[InputEvent]) -> Result<()> {
+        if let Some(writer) = as_mut!(self impl EventWriter) {
+            return writer.send_events(events);
+        }
+
+        for event in events {
+            match *event {
+                InputEvent::KeyDown(key) => as_mut!(self impl KeyboardWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .send_key_down(key)?,
+                InputEvent::KeyUp(key) => as_mut!(self impl KeyboardWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .send_key_up(key)?,
+                InputEvent::ButtonDown(button) => as_mut!(self impl MouseWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .send_button_down(button)?,
+                InputEvent::ButtonUp(button) => as_mut!(self impl MouseWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .send_button_up(button)?,
+                InputEvent::MoveRelative { x, y } => as_mut!(self impl MouseWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .mouse_move_relative(x, y)?,
+                InputEvent::MoveAbsolute { x, y } => as_mut!(self impl MouseAbsoluteWriter)
+                    .ok_or(InputFlowError::Unsupported)?
+                    .mouse_move_absolute(x, y)?,
+                InputEvent::Wheel(notches) => {
+                    let writer =
+                        as_mut!(self impl MouseWriter).ok_or(InputFlowError::Unsupported)?;
+                    let button = if notches > 0 {
+                        MouseButton::ScrollUp
+                    } else {
+                        MouseButton::ScrollDown
+                    };
+                    for _ in 0..notches.unsigned_abs() {
+                        writer.click_button(button)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Safe plugin loading api.
+//!
+//! [`load`] wraps the raw [`load_plugin`](crate::load_plugin) entry point: it finds and verifies
+//! the library, creates the plugin and hands back a [`PluginHandle`] that keeps the library
+//! loaded for as long as it lives.
+
+use std::path::PathBuf;
+
+use cglue::prelude::v1::{trait_group::c_void, *};
+
+use crate::{
+    api_traits::*,
+    error::InputFlowError,
+    headers::FeatureSupport,
+    plugin_path::{find_plugin, PluginNotFound},
+    PluginInner, PluginInnerArcBox,
+};
+
+/// Everything that can go wrong while loading a plugin
+#[derive(Debug)]
+pub enum LoadError {
+    /// No library was found for the plugin name
+    NotFound(PluginNotFound),
+    /// The library exists but could not be opened
+    Library {
+        path: PathBuf,
+        source: libloading::Error,
+    },
+    /// The library does not export an `IF_PLUGIN_HEAD`
+    NotAPlugin {
+        path: PathBuf,
+        source: libloading::Error,
+    },
+    /// The plugin was built against an incompatible version of inputflow
+    Abi { path: PathBuf },
+    /// The arguments were rejected before reaching the plugin
+    Arguments(String),
+    /// The plugin itself failed to start
+    Create {
+        error: InputFlowError,
+        /// Details the plugin reported through its last error
+        message: Option<String>,
+    },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::NotFound(e) => write!(f, "{e}"),
+            LoadError::Library { path, source } => {
+                write!(f, "failed to open {}: {source}", path.display())
+            }
+            LoadError::NotAPlugin { path, source } => {
+                write!(f, "{} is not an inputflow plugin: {source}", path.display())
+            }
+            LoadError::Abi { path } => write!(
+                f,
+                "{} was built against an incompatible version of inputflow",
+                path.display()
+            ),
+            LoadError::Arguments(e) => write!(f, "invalid plugin arguments: {e}"),
+            LoadError::Create {
+                error,
+                message: Some(message),
+            } => write!(f, "plugin failed to start: {error}: {message}"),
+            LoadError::Create { error, .. } => write!(f, "plugin failed to start: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::NotFound(e) => Some(e),
+            LoadError::Library { source, .. } | LoadError::NotAPlugin { source, .. } => {
+                Some(source)
+            }
+            LoadError::Create { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LoadError> for InputFlowError {
+    fn from(e: LoadError) -> Self {
+        match e {
+            LoadError::NotFound(_) => InputFlowError::Path,
+            LoadError::Library { .. } => InputFlowError::Loading,
+            LoadError::NotAPlugin { .. } => InputFlowError::Symbol,
+            LoadError::Abi { .. } => InputFlowError::Abi,
+            LoadError::Arguments(_) => InputFlowError::Parameter,
+            LoadError::Create { error, .. } => error,
+        }
+    }
+}
+
+/// The features object hosts get from a loaded plugin
+pub type PluginFeatures = ControllerFeatures<'static, CBox<'static, c_void>, CArc<c_void>>;
+
+/// A loaded plugin instance.
+///
+/// Owns the plugin library, which stays loaded until the handle is dropped.
+/// The typed accessors return `None` when the plugin does not implement the trait.
+/// Everything else on [`ControllerFeatures`] is reachable through `Deref`.
+pub struct PluginHandle {
+    features: PluginFeatures,
+}
+
+impl PluginHandle {
+    /// Name reported by the plugin
+    pub fn name(&self) -> String {
+        self.features.name().into()
+    }
+
+    /// Features the plugin declares, unknown bits are dropped
+    pub fn features(&self) -> FeatureSupport {
+        FeatureSupport::from_bits_truncate(self.features.capabilities())
+    }
+
+    /// Returns and clears the message the plugin recorded for the last error it returned on this thread
+    pub fn last_error(&self) -> Option<String> {
+        Some(self.features.last_error().into_string()).filter(|m| !m.is_empty())
+    }
+
+    pub fn mouse(&mut self) -> Option<&mut (impl MouseWriter + '_)> {
+        as_mut!(self.features impl MouseWriter)
+    }
+
+    pub fn mouse_absolute(&mut self) -> Option<&mut (impl MouseAbsoluteWriter + '_)> {
+        as_mut!(self.features impl MouseAbsoluteWriter)
+    }
+
+    pub fn smooth_mouse(&mut self) -> Option<&mut (impl SmoothMouseWriter + '_)> {
+        as_mut!(self.features impl SmoothMouseWriter)
+    }
+
+    pub fn mouse_reader(&mut self) -> Option<&mut (impl MouseReader + '_)> {
+        as_mut!(self.features impl MouseReader)
+    }
+
+    pub fn keyboard(&mut self) -> Option<&mut (impl KeyboardWriter + '_)> {
+        as_mut!(self.features impl KeyboardWriter)
+    }
+
+    pub fn keyboard_reader(&mut self) -> Option<&mut (impl KeyboardReader + '_)> {
+        as_mut!(self.features impl KeyboardReader)
+    }
+
+    pub fn interceptor(&mut self) -> Option<&mut (impl InputInterceptor + '_)> {
+        as_mut!(self.features impl InputInterceptor)
+    }
+
+    pub fn connection_monitor(&mut self) -> Option<&mut (impl ConnectionMonitor + '_)> {
+        as_mut!(self.features impl ConnectionMonitor)
+    }
+
+    /// Releases the handle into the underlying cglue features object
+    pub fn into_features(self) -> PluginFeatures {
+        self.features
+    }
+}
+
+impl From<PluginInnerArcBox<'static>> for PluginHandle {
+    fn from(plugin: PluginInnerArcBox<'static>) -> Self {
+        Self {
+            features: plugin.into_features(),
+        }
+    }
+}
+
+impl std::ops::Deref for PluginHandle {
+    type Target = PluginFeatures;
+
+    fn deref(&self) -> &Self::Target {
+        &self.features
+    }
+}
+
+impl std::ops::DerefMut for PluginHandle {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.features
+    }
+}
+
+/// Loads a plugin by name or library path and creates it with `args`.
+///
+/// Names are looked up as described in [`plugin_path`](crate::plugin_path).
+/// The library is trusted to be a well behaved inputflow plugin once its layout has been verified,
+/// only put trusted libraries in the plugin directories.
+pub fn load(name: &str, args: &str) -> Result<PluginHandle, LoadError> {
+    let path = find_plugin(name).map_err(LoadError::NotFound)?;
+    // safety: see the trust requirement documented above
+    let (lib, header) = unsafe { crate::load_library(&path)? };
+    crate::create_plugin(CArc::from(lib), header, args).map(PluginHandle::from)
+}
@@ -1,7 +1,11 @@
-use abi_stable::type_layout::TypeLayout;
+use abi_stable::{
+    std_types::{ROption, RSlice, RStr, RString, RVec},
+    type_layout::TypeLayout,
+    StableAbi,
+};
 use cglue::prelude::v1::*;
 
-use crate::{error::Result, PluginInnerArcBox};
+use crate::{error::Result, logger::PluginLogger, PluginInnerArcBox};
 
 bitflags::bitflags! {
     /// Defines what features this plugin supports
@@ -29,16 +33,76 @@ bitflags::bitflags! {
 //     const LAYOUT: &'static TypeLayout = TypeLayout::ABI_CONSTS;
 // }
 
+/// Describes one argument a plugin accepts when it is created
+#[repr(C)]
+#[derive(Debug, Clone, Copy, StableAbi)]
+pub struct ArgDescriptor {
+    pub name: RStr<'static>,
+    /// Type of the value: `bool`, an integer type such as `u32`, `String`, or `Option` of one of them
+    pub type_name: RStr<'static>,
+    /// Default value written in argument syntax, empty if the argument has to be given
+    pub default: RStr<'static>,
+    pub description: RStr<'static>,
+}
+
+impl ArgDescriptor {
+    pub const fn new(
+        name: &'static str,
+        type_name: &'static str,
+        default: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name: RStr::from_str(name),
+            type_name: RStr::from_str(type_name),
+            default: RStr::from_str(default),
+            description: RStr::from_str(description),
+        }
+    }
+}
+
+/// A device a plugin found and can connect to, see [`PluginHeader::list_devices`]
+#[repr(C)]
+#[derive(Debug, Clone, StableAbi)]
+pub struct DeviceDescriptor {
+    /// Arguments that make `create` connect to this device, e.g. `auto_select=false,com_port="/dev/ttyUSB1"`
+    pub args: RString,
+    /// Human readable description, such as the port and USB ids
+    pub description: RString,
+}
+
 /// Plugin header that the API looks for.
 ///
 /// Plugins should define the header with name `PLUGIN_HEADER` with no mangling.
 /// the function interface exposed by plugins, "create", accepts a reference to the library interface
-/// as well as a raw ffi c_str (char ptr) to the arguments for plugin init
-/// each plugin may decide how to parse these arguments themselves and must provide documentation on their usage.
+/// as well as a raw ffi c_str (char ptr) to the arguments for plugin init.
+/// The argument string stays owned by the host and is only valid during the call,
+/// plugins should parse it with [`ConnectorArgs::from_raw`](crate::args::ConnectorArgs::from_raw).
+/// The last argument is the host logger, plugins should install it with [`PluginLogger::init`] before logging anything.
 #[repr(C)]
 pub struct PluginHeader {
     pub features: FeatureSupport,
     pub layout: &'static TypeLayout,
-    #[allow(improper_ctypes_definitions)] // the linter is being stupid and not noticing the repr(u8)
-    pub create: extern "C" fn(&CArc<cglue::trait_group::c_void>, *const std::ffi::c_char) -> Result<PluginInnerArcBox<'static>>,
+    #[allow(improper_ctypes_definitions)]
+    // the linter is being stupid and not noticing the repr(u8)
+    pub create: extern "C" fn(
+        &CArc<cglue::trait_group::c_void>,
+        *const std::ffi::c_char,
+        Option<&PluginLogger>,
+    ) -> Result<PluginInnerArcBox<'static>>,
+    /// Short connector name used to pick the plugin from an [`Inventory`](crate::inventory::Inventory), e.g. `kmbox`
+    pub name: RStr<'static>,
+    /// Version of the plugin crate
+    pub version: RStr<'static>,
+    /// Human readable description of what the plugin controls
+    pub description: RStr<'static>,
+    /// Every argument the plugin accepts in `create`
+    pub args: RSlice<'static, ArgDescriptor>,
+    /// Returns and clears the message of the last error, used to explain a failed `create`.
+    /// Plugins set this to [`plugin_last_error`](crate::error::plugin_last_error)
+    pub last_error: extern "C" fn() -> ROption<RString>,
+    /// Lists the devices the plugin can connect to right now, `None` if it cannot discover devices.
+    /// A failure is explained by `last_error` like one of `create`.
+    #[allow(improper_ctypes_definitions)]
+    pub list_devices: Option<extern "C" fn() -> Result<RVec<DeviceDescriptor>>>,
 }
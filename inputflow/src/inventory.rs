@@ -0,0 +1,301 @@
+//! Discovery of the plugins installed on the system.
+//!
+//! Modelled after memflow's inventory: every library in the plugin directories is opened,
+//! checked for an `IF_PLUGIN_HEAD` with a matching layout, and kept loaded so connectors
+//! can be created from it without searching again.
+
+use std::path::{Path, PathBuf};
+
+use cglue::prelude::v1::*;
+use libloading::Library;
+
+use crate::{
+    args::{ArgSpec, ConnectorArgs},
+    error::InputFlowError,
+    handle::{LoadError, PluginHandle},
+    headers::{ArgDescriptor, DeviceDescriptor, FeatureSupport, PluginHeader},
+    plugin_path::{plugin_search_paths, PluginNotFound},
+    PluginInnerArcBox,
+};
+
+/// Owned copy of an [`ArgDescriptor`]
+#[derive(Debug, Clone)]
+pub struct ArgInfo {
+    pub name: String,
+    pub type_name: String,
+    /// Default value in argument syntax, empty if the argument has to be given
+    pub default: String,
+    pub description: String,
+}
+
+impl From<&ArgDescriptor> for ArgInfo {
+    fn from(arg: &ArgDescriptor) -> Self {
+        Self {
+            name: arg.name.as_str().to_string(),
+            type_name: arg.type_name.as_str().to_string(),
+            default: arg.default.as_str().to_string(),
+            description: arg.description.as_str().to_string(),
+        }
+    }
+}
+
+/// Owned copy of a [`DeviceDescriptor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Arguments that make [`Inventory::create_connector`] connect to this device
+    pub args: String,
+    pub description: String,
+}
+
+impl From<&DeviceDescriptor> for DeviceInfo {
+    fn from(device: &DeviceDescriptor) -> Self {
+        Self {
+            args: device.args.to_string(),
+            description: device.description.to_string(),
+        }
+    }
+}
+
+/// Description of a plugin found by the [`Inventory`]
+#[derive(Debug, Clone)]
+pub struct PluginInfo {
+    /// Connector name, as passed to [`Inventory::create_connector`]
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub features: FeatureSupport,
+    /// Every argument the plugin accepts
+    pub args: Vec<ArgInfo>,
+    /// Whether [`Inventory::list_devices`] can find devices for the plugin
+    pub lists_devices: bool,
+    /// The library file the plugin was loaded from
+    pub path: PathBuf,
+}
+
+impl PluginInfo {
+    /// Formats a `--help` style text describing the plugin and its arguments
+    pub fn help(&self) -> String {
+        let mut help = format!("{} v{}\n{}\n", self.name, self.version, self.description);
+
+        if self.args.is_empty() {
+            help.push_str("\nThis connector takes no arguments.\n");
+            return help;
+        }
+
+        help.push_str("\nArguments:\n");
+        for arg in &self.args {
+            if arg.default.is_empty() {
+                help.push_str(&format!("  {}: {} (required)\n", arg.name, arg.type_name));
+            } else {
+                help.push_str(&format!(
+                    "  {}: {} = {}\n",
+                    arg.name, arg.type_name, arg.default
+                ));
+            }
+            for line in arg.description.lines() {
+                help.push_str(&format!("      {}\n", line));
+            }
+        }
+        help
+    }
+
+    /// Checks `args` against the arguments the plugin declares without creating it.
+    ///
+    /// Returns a message naming the first unknown argument, value of the wrong type
+    /// or missing required argument.
+    pub fn validate_args(&self, args: &str) -> std::result::Result<(), String> {
+        ConnectorArgs::parse(args)
+            .and_then(|parsed| parsed.validate(&self.args))
+            .map_err(|e| format!("{e} for connector {}", self.name))
+    }
+}
+
+impl ArgSpec for ArgInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    fn has_default(&self) -> bool {
+        !self.default.is_empty()
+    }
+}
+
+struct LibInstance {
+    info: PluginInfo,
+    lib: CArc<Library>,
+    header: &'static PluginHeader,
+}
+
+/// Collection of all plugins found in the scanned directories
+#[derive(Default)]
+pub struct Inventory {
+    plugins: Vec<LibInstance>,
+}
+
+impl Inventory {
+    /// Creates an inventory of every plugin in the default search directories.
+    ///
+    /// # Safety
+    ///
+    /// Every library in the plugin directories is loaded, which runs its initialization code.
+    /// Only use this on directories that contain trusted libraries.
+    pub unsafe fn scan() -> Self {
+        let mut inventory = Self::default();
+        for dir in plugin_search_paths() {
+            inventory.add_dir(&dir);
+        }
+        inventory
+    }
+
+    /// Creates an inventory of the plugins in a single directory.
+    ///
+    /// # Safety
+    ///
+    /// See [`Inventory::scan`].
+    pub unsafe fn scan_path(path: impl AsRef<Path>) -> Self {
+        let mut inventory = Self::default();
+        inventory.add_dir(path.as_ref());
+        inventory
+    }
+
+    /// Adds every valid plugin in `dir` to the inventory and returns how many were added.
+    /// Libraries that are not inputflow plugins or were built against an incompatible version are skipped.
+    ///
+    /// # Safety
+    ///
+    /// See [`Inventory::scan`].
+    pub unsafe fn add_dir(&mut self, dir: &Path) -> usize {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return 0;
+        };
+
+        let mut added = 0;
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path
+                .extension()
+                .is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+                && self.add_file(&path).is_ok()
+            {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Adds a single plugin library to the inventory.
+    ///
+    /// A library that was already added from the same path is not loaded again.
+    ///
+    /// # Safety
+    ///
+    /// See [`Inventory::scan`].
+    pub unsafe fn add_file(&mut self, path: &Path) -> Result<&PluginInfo, LoadError> {
+        let canonical = path.canonicalize().map_err(|_| {
+            LoadError::NotFound(PluginNotFound {
+                name: path.display().to_string(),
+                tried: vec![path.to_path_buf()],
+            })
+        })?;
+
+        if let Some(idx) = self.plugins.iter().position(|p| p.info.path == canonical) {
+            return Ok(&self.plugins[idx].info);
+        }
+
+        let (lib, header) = crate::load_library(&canonical)?;
+
+        self.plugins.push(LibInstance {
+            info: PluginInfo {
+                name: header.name.as_str().to_string(),
+                version: header.version.as_str().to_string(),
+                description: header.description.as_str().to_string(),
+                features: header.features,
+                args: header.args.as_slice().iter().map(ArgInfo::from).collect(),
+                lists_devices: header.list_devices.is_some(),
+                path: canonical,
+            },
+            lib: CArc::from(lib),
+            header,
+        });
+        Ok(&self.plugins.last().unwrap().info)
+    }
+
+    /// Returns the description of a connector by its name
+    pub fn plugin_info(&self, name: &str) -> Option<&PluginInfo> {
+        self.available_plugins().find(|p| p.name == name)
+    }
+
+    /// Lists every plugin that was found
+    pub fn available_plugins(&self) -> impl Iterator<Item = &PluginInfo> {
+        self.plugins.iter().map(|p| &p.info)
+    }
+
+    /// Returns the names of all connectors that can be created
+    pub fn available_connectors(&self) -> Vec<String> {
+        self.plugins.iter().map(|p| p.info.name.clone()).collect()
+    }
+
+    /// Lists the devices a connector can connect to right now, asking the plugin through its header.
+    ///
+    /// Fails with [`InputFlowError::Unsupported`] if the plugin cannot discover devices.
+    /// On failure a description of the error is available from
+    /// [`take_last_error`](crate::error::take_last_error).
+    pub fn list_devices(&self, name: &str) -> Result<Vec<DeviceInfo>, InputFlowError> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.info.name == name)
+            .ok_or_else(|| {
+                InputFlowError::Path.with_message(format!("no connector named {name} was found"))
+            })?;
+        let list_devices = plugin.header.list_devices.ok_or_else(|| {
+            InputFlowError::Unsupported
+                .with_message(format!("connector {name} cannot list its devices"))
+        })?;
+
+        match list_devices() {
+            Ok(devices) => Ok(devices.iter().map(DeviceInfo::from).collect()),
+            Err(error) => Err(match (plugin.header.last_error)().into_option() {
+                Some(message) => error.with_message(message),
+                None => error,
+            }),
+        }
+    }
+
+    /// Creates a connector by its name, passing `args` to the plugin.
+    ///
+    /// If several libraries provide the same connector name, the first one found wins,
+    /// which follows the order of the search path.
+    /// The arguments are validated with [`PluginInfo::validate_args`] before the plugin sees them.
+    pub fn create_connector(
+        &self,
+        name: &str,
+        args: &str,
+    ) -> Result<PluginInnerArcBox<'static>, LoadError> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.info.name == name)
+            .ok_or_else(|| {
+                LoadError::NotFound(PluginNotFound {
+                    name: name.to_string(),
+                    tried: self.plugins.iter().map(|p| p.info.path.clone()).collect(),
+                })
+            })?;
+
+        plugin
+            .info
+            .validate_args(args)
+            .map_err(LoadError::Arguments)?;
+
+        crate::create_plugin(plugin.lib.clone(), plugin.header, args)
+    }
+
+    /// Creates a connector by its name and wraps it in a [`PluginHandle`]
+    pub fn load(&self, name: &str, args: &str) -> Result<PluginHandle, LoadError> {
+        self.create_connector(name, args).map(PluginHandle::from)
+    }
+}
@@ -3,10 +3,12 @@
 
 use abi_stable::StableAbi;
 
+use crate::error::InputFlowError;
+
 /// Mouse Buttons are a simple byte enum that gets passed around
 /// Values mostly align with the microsoft VKEY spec
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, StableAbi)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StableAbi)]
 pub enum MouseButton {
     NULL,
     Left,
@@ -28,11 +30,73 @@ impl std::fmt::Display for MouseButton {
 }
 
 /// Keyboard keys are also represented by a byte
-/// Values mostly align with the microsoft VKEY spec
+/// Values align with the microsoft VKEY spec, with the mouse button codes (0x01, 0x02, 0x04-0x06) left out
+/// since those live in [`MouseButton`].
+/// Variant names follow the `VK_*` constant names with the prefix dropped.
+/// Digit keys have no constant of their own in the spec and are named `KEY_0` to `KEY_9`.
+/// https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, StableAbi)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StableAbi)]
 pub enum KeyboardKey {
-    NULL,
+    NULL = 0x00,
+    /// Control-break processing
+    CANCEL = 0x03,
+    /// BACKSPACE key
+    BACK = 0x08,
+    TAB = 0x09,
+    CLEAR = 0x0C,
+    /// ENTER key
+    RETURN = 0x0D,
+    SHIFT = 0x10,
+    CONTROL = 0x11,
+    /// ALT key
+    MENU = 0x12,
+    PAUSE = 0x13,
+    /// CAPS LOCK key
+    CAPITAL = 0x14,
+    /// IME Kana mode. Same code as IME Hangul mode, see [`KeyboardKey::HANGUL`]
+    KANA = 0x15,
+    IME_ON = 0x16,
+    JUNJA = 0x17,
+    FINAL = 0x18,
+    /// IME Hanja mode. Same code as IME Kanji mode, see [`KeyboardKey::KANJI`]
+    HANJA = 0x19,
+    IME_OFF = 0x1A,
+    ESCAPE = 0x1B,
+    CONVERT = 0x1C,
+    NONCONVERT = 0x1D,
+    ACCEPT = 0x1E,
+    MODECHANGE = 0x1F,
+    SPACE = 0x20,
+    /// PAGE UP key
+    PRIOR = 0x21,
+    /// PAGE DOWN key
+    NEXT = 0x22,
+    END = 0x23,
+    HOME = 0x24,
+    LEFT = 0x25,
+    UP = 0x26,
+    RIGHT = 0x27,
+    DOWN = 0x28,
+    SELECT = 0x29,
+    PRINT = 0x2A,
+    EXECUTE = 0x2B,
+    /// PRINT SCREEN key
+    SNAPSHOT = 0x2C,
+    INSERT = 0x2D,
+    DELETE = 0x2E,
+    HELP = 0x2F,
+    KEY_0 = 0x30, // ascii code for '0'. Same as VKEY
+    KEY_1,
+    KEY_2,
+    KEY_3,
+    KEY_4,
+    KEY_5,
+    KEY_6,
+    KEY_7,
+    KEY_8,
+    KEY_9,
     A = 0x41, // ascii code for 'A'. Same as VKEY
     B,
     C,
@@ -74,6 +138,147 @@ pub enum KeyboardKey {
     NUMPAD7,
     NUMPAD8,
     NUMPAD9,
+    MULTIPLY,
+    ADD,
+    SEPARATOR,
+    SUBTRACT,
+    DECIMAL,
+    DIVIDE,
+    F1 = 0x70,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+    NUMLOCK = 0x90,
+    SCROLL = 0x91,
+    LSHIFT = 0xA0,
+    RSHIFT,
+    LCONTROL,
+    RCONTROL,
+    /// Left ALT key
+    LMENU,
+    /// Right ALT key (AltGr on most european layouts)
+    RMENU,
+    BROWSER_BACK = 0xA6,
+    BROWSER_FORWARD,
+    BROWSER_REFRESH,
+    BROWSER_STOP,
+    BROWSER_SEARCH,
+    BROWSER_FAVORITES,
+    BROWSER_HOME,
+    VOLUME_MUTE = 0xAD,
+    VOLUME_DOWN,
+    VOLUME_UP,
+    MEDIA_NEXT_TRACK = 0xB0,
+    MEDIA_PREV_TRACK,
+    MEDIA_STOP,
+    MEDIA_PLAY_PAUSE,
+    LAUNCH_MAIL = 0xB4,
+    LAUNCH_MEDIA_SELECT,
+    LAUNCH_APP1,
+    LAUNCH_APP2,
+    /// `;:` on the US layout
+    OEM_1 = 0xBA,
+    /// `=+` on any layout
+    OEM_PLUS,
+    /// `,<` on any layout
+    OEM_COMMA,
+    /// `-_` on any layout
+    OEM_MINUS,
+    /// `.>` on any layout
+    OEM_PERIOD,
+    /// `/?` on the US layout
+    OEM_2,
+    /// `` `~ `` on the US layout
+    OEM_3,
+    /// `[{` on the US layout
+    OEM_4 = 0xDB,
+    /// `\|` on the US layout
+    OEM_5,
+    /// `]}` on the US layout
+    OEM_6,
+    /// `'"` on the US layout
+    OEM_7,
+    OEM_8,
+    /// The extra key next to left shift on 102 key (ISO) keyboards
+    OEM_102 = 0xE2,
+    PROCESSKEY = 0xE5,
+    PACKET = 0xE7,
+    ATTN = 0xF6,
+    CRSEL,
+    EXSEL,
+    EREOF,
+    PLAY,
+    ZOOM,
+    NONAME,
+    PA1,
+    OEM_CLEAR,
+}
+
+impl KeyboardKey {
+    /// IME Hangul mode. Alias of [`KeyboardKey::KANA`]
+    pub const HANGUL: Self = Self::KANA;
+    /// IME Kanji mode. Alias of [`KeyboardKey::HANJA`]
+    pub const KANJI: Self = Self::HANJA;
+}
+
+impl TryFrom<u8> for KeyboardKey {
+    type Error = InputFlowError;
+
+    /// Converts a raw virtual keycode back into a key.
+    /// Fails with [`InputFlowError::InvalidKey`] for codes that are unassigned,
+    /// reserved, OEM specific or belong to the mouse buttons.
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            0x00
+            | 0x03
+            | 0x08..=0x09
+            | 0x0C..=0x0D
+            | 0x10..=0x1F
+            | 0x20..=0x39
+            | 0x41..=0x5D
+            | 0x5F..=0x6F
+            | 0x70..=0x87
+            | 0x90..=0x91
+            | 0xA0..=0xB7
+            | 0xBA..=0xC0
+            | 0xDB..=0xDF
+            | 0xE2
+            | 0xE5
+            | 0xE7
+            | 0xF6..=0xFE => {
+                // safety:
+                // every code in the ranges above has a matching variant in this repr(u8) enum
+                Ok(unsafe { std::mem::transmute::<u8, KeyboardKey>(code) })
+            }
+            _ => Err(InputFlowError::InvalidKey),
+        }
+    }
+}
+
+impl From<KeyboardKey> for u8 {
+    fn from(key: KeyboardKey) -> Self {
+        key as u8
+    }
 }
 
 impl std::fmt::Display for KeyboardKey {
@@ -81,3 +286,53 @@ impl std::fmt::Display for KeyboardKey {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Codes the VK table leaves unassigned, reserved or OEM specific, plus the mouse buttons
+    fn rejected(code: u8) -> bool {
+        matches!(
+            code,
+            0x01..=0x02
+                | 0x04..=0x07
+                | 0x0A..=0x0B
+                | 0x0E..=0x0F
+                | 0x3A..=0x40
+                | 0x5E
+                | 0x88..=0x8F
+                | 0x92..=0x9F
+                | 0xB8..=0xB9
+                | 0xC1..=0xDA
+                | 0xE0..=0xE1
+                | 0xE3..=0xE4
+                | 0xE6
+                | 0xE8..=0xF5
+                | 0xFF
+        )
+    }
+
+    #[test]
+    fn every_code_converts_or_is_rejected() {
+        for code in 0..=u8::MAX {
+            match KeyboardKey::try_from(code) {
+                Ok(key) => {
+                    assert!(!rejected(code), "{code:#04x} converted to {key:?}");
+                    assert_eq!(u8::from(key), code);
+                }
+                Err(e) => {
+                    assert!(rejected(code), "{code:#04x} was rejected");
+                    assert_eq!(e, InputFlowError::InvalidKey);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_code_is_rejected() {
+        assert_eq!(KeyboardKey::_Reserved as u8, 0x5E);
+        assert_eq!(KeyboardKey::try_from(0x5E), Err(InputFlowError::InvalidKey));
+        assert_eq!(KeyboardKey::try_from(0x5F), Ok(KeyboardKey::SLEEP));
+    }
+}
@@ -0,0 +1,469 @@
+//! Keyboard layout tables used to type text through any [`KeyboardWriter`].
+//!
+//! Keys are named after their position on a US keyboard (the same way the hardware backends
+//! treat [`KeyboardKey`]), so a layout table tells which physical key and modifier produce a character
+//! on a target that is configured for that layout.
+//! Characters that only exist as dead keys on a layout (such as `^` on German) are not mapped.
+
+use crate::{api_traits::KeyboardWriter, error::InputFlowError, key_types::KeyboardKey};
+
+/// Modifier that has to be held while pressing a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Modifier {
+    None,
+    Shift,
+    /// Right alt, used for the third level of european layouts
+    AltGr,
+}
+
+impl Modifier {
+    /// The key that has to be held for this modifier
+    pub fn key(&self) -> Option<KeyboardKey> {
+        match self {
+            Modifier::None => None,
+            Modifier::Shift => Some(KeyboardKey::LSHIFT),
+            Modifier::AltGr => Some(KeyboardKey::RMENU),
+        }
+    }
+}
+
+/// A key press that produces one character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyStroke {
+    pub key: KeyboardKey,
+    pub modifier: Modifier,
+}
+
+impl KeyStroke {
+    pub const fn new(key: KeyboardKey, modifier: Modifier) -> Self {
+        Self { key, modifier }
+    }
+}
+
+/// Maps characters to the key strokes producing them.
+///
+/// Implement this to support layouts that are not built in.
+pub trait KeyboardLayout {
+    /// Returns the key stroke producing `c`, or `None` if the layout cannot type it
+    fn keystroke(&self, c: char) -> Option<KeyStroke>;
+}
+
+use KeyboardKey::*;
+use Modifier::{AltGr, Shift};
+
+/// Characters typed the same way on every built in layout
+fn common_keystroke(c: char) -> Option<KeyStroke> {
+    Some(match c {
+        ' ' => KeyStroke::new(SPACE, Modifier::None),
+        '\n' => KeyStroke::new(RETURN, Modifier::None),
+        '\t' => KeyStroke::new(TAB, Modifier::None),
+        _ => return None,
+    })
+}
+
+/// Looks `c` up in a layout table of (key, unshifted, shifted, altgr) characters.
+/// `'\0'` marks a level that produces no character.
+fn table_keystroke(
+    table: &[(KeyboardKey, char, char, Option<char>)],
+    c: char,
+) -> Option<KeyStroke> {
+    table.iter().find_map(|&(key, plain, shifted, altgr)| {
+        if c == plain {
+            Some(KeyStroke::new(key, Modifier::None))
+        } else if c == shifted {
+            Some(KeyStroke::new(key, Shift))
+        } else if Some(c) == altgr {
+            Some(KeyStroke::new(key, AltGr))
+        } else {
+            None
+        }
+    })
+}
+
+/// Maps ascii letters, with `letters` listing the key for 'a' to 'z' in order
+fn letter_keystroke(letters: &[KeyboardKey; 26], c: char) -> Option<KeyStroke> {
+    if !c.is_ascii_alphabetic() {
+        return None;
+    }
+    let key = letters[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+    let modifier = if c.is_ascii_uppercase() {
+        Shift
+    } else {
+        Modifier::None
+    };
+    Some(KeyStroke::new(key, modifier))
+}
+
+const QWERTY_LETTERS: [KeyboardKey; 26] = [
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+];
+const QWERTZ_LETTERS: [KeyboardKey; 26] = [
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Z, Y,
+];
+const AZERTY_LETTERS: [KeyboardKey; 26] = [
+    Q, B, C, D, E, F, G, H, I, J, K, L, OEM_1, N, O, P, A, R, S, T, U, V, Z, X, Y, W,
+];
+
+/// US (QWERTY) layout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsLayout;
+
+const US_TABLE: &[(KeyboardKey, char, char, Option<char>)] = &[
+    (OEM_3, '`', '~', None),
+    (KEY_1, '1', '!', None),
+    (KEY_2, '2', '@', None),
+    (KEY_3, '3', '#', None),
+    (KEY_4, '4', '$', None),
+    (KEY_5, '5', '%', None),
+    (KEY_6, '6', '^', None),
+    (KEY_7, '7', '&', None),
+    (KEY_8, '8', '*', None),
+    (KEY_9, '9', '(', None),
+    (KEY_0, '0', ')', None),
+    (OEM_MINUS, '-', '_', None),
+    (OEM_PLUS, '=', '+', None),
+    (OEM_4, '[', '{', None),
+    (OEM_6, ']', '}', None),
+    (OEM_5, '\\', '|', None),
+    (OEM_1, ';', ':', None),
+    (OEM_7, '\'', '"', None),
+    (OEM_COMMA, ',', '<', None),
+    (OEM_PERIOD, '.', '>', None),
+    (OEM_2, '/', '?', None),
+];
+
+impl KeyboardLayout for UsLayout {
+    fn keystroke(&self, c: char) -> Option<KeyStroke> {
+        common_keystroke(c)
+            .or_else(|| letter_keystroke(&QWERTY_LETTERS, c))
+            .or_else(|| table_keystroke(US_TABLE, c))
+    }
+}
+
+/// UK (QWERTY, ISO) layout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UkLayout;
+
+const UK_TABLE: &[(KeyboardKey, char, char, Option<char>)] = &[
+    (OEM_3, '`', '¬', Some('¦')),
+    (KEY_1, '1', '!', None),
+    (KEY_2, '2', '"', None),
+    (KEY_3, '3', '£', None),
+    (KEY_4, '4', '$', Some('€')),
+    (KEY_5, '5', '%', None),
+    (KEY_6, '6', '^', None),
+    (KEY_7, '7', '&', None),
+    (KEY_8, '8', '*', None),
+    (KEY_9, '9', '(', None),
+    (KEY_0, '0', ')', None),
+    (OEM_MINUS, '-', '_', None),
+    (OEM_PLUS, '=', '+', None),
+    (OEM_4, '[', '{', None),
+    (OEM_6, ']', '}', None),
+    (OEM_1, ';', ':', None),
+    (OEM_7, '\'', '@', None),
+    (OEM_5, '#', '~', None),
+    (OEM_102, '\\', '|', None),
+    (OEM_COMMA, ',', '<', None),
+    (OEM_PERIOD, '.', '>', None),
+    (OEM_2, '/', '?', None),
+];
+
+impl KeyboardLayout for UkLayout {
+    fn keystroke(&self, c: char) -> Option<KeyStroke> {
+        common_keystroke(c)
+            .or_else(|| letter_keystroke(&QWERTY_LETTERS, c))
+            .or_else(|| table_keystroke(UK_TABLE, c))
+    }
+}
+
+/// German (QWERTZ) layout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GermanLayout;
+
+const GERMAN_TABLE: &[(KeyboardKey, char, char, Option<char>)] = &[
+    (OEM_3, '\0', '°', None),
+    (KEY_1, '1', '!', None),
+    (KEY_2, '2', '"', Some('²')),
+    (KEY_3, '3', '§', Some('³')),
+    (KEY_4, '4', '$', None),
+    (KEY_5, '5', '%', None),
+    (KEY_6, '6', '&', None),
+    (KEY_7, '7', '/', Some('{')),
+    (KEY_8, '8', '(', Some('[')),
+    (KEY_9, '9', ')', Some(']')),
+    (KEY_0, '0', '=', Some('}')),
+    (OEM_MINUS, 'ß', '?', Some('\\')),
+    (Q, '\0', '\0', Some('@')),
+    (E, '\0', '\0', Some('€')),
+    (M, '\0', '\0', Some('µ')),
+    (OEM_4, 'ü', 'Ü', None),
+    (OEM_6, '+', '*', Some('~')),
+    (OEM_1, 'ö', 'Ö', None),
+    (OEM_7, 'ä', 'Ä', None),
+    (OEM_5, '#', '\'', None),
+    (OEM_102, '<', '>', Some('|')),
+    (OEM_COMMA, ',', ';', None),
+    (OEM_PERIOD, '.', ':', None),
+    (OEM_2, '-', '_', None),
+];
+
+impl KeyboardLayout for GermanLayout {
+    fn keystroke(&self, c: char) -> Option<KeyStroke> {
+        common_keystroke(c)
+            .or_else(|| letter_keystroke(&QWERTZ_LETTERS, c))
+            .or_else(|| table_keystroke(GERMAN_TABLE, c))
+    }
+}
+
+/// French (AZERTY) layout
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrenchAzertyLayout;
+
+const FRENCH_AZERTY_TABLE: &[(KeyboardKey, char, char, Option<char>)] = &[
+    (OEM_3, '²', '\0', None),
+    (KEY_1, '&', '1', None),
+    (KEY_2, 'é', '2', None),
+    (KEY_3, '"', '3', Some('#')),
+    (KEY_4, '\'', '4', Some('{')),
+    (KEY_5, '(', '5', Some('[')),
+    (KEY_6, '-', '6', Some('|')),
+    (KEY_7, 'è', '7', None),
+    (KEY_8, '_', '8', Some('\\')),
+    (KEY_9, 'ç', '9', Some('^')),
+    (KEY_0, 'à', '0', Some('@')),
+    (OEM_MINUS, ')', '°', Some(']')),
+    (OEM_PLUS, '=', '+', Some('}')),
+    (E, '\0', '\0', Some('€')),
+    (OEM_6, '$', '£', Some('¤')),
+    (OEM_7, 'ù', '%', None),
+    (OEM_5, '*', 'µ', None),
+    (OEM_102, '<', '>', None),
+    (M, ',', '?', None),
+    (OEM_COMMA, ';', '.', None),
+    (OEM_PERIOD, ':', '/', None),
+    (OEM_2, '!', '§', None),
+];
+
+impl KeyboardLayout for FrenchAzertyLayout {
+    fn keystroke(&self, c: char) -> Option<KeyStroke> {
+        common_keystroke(c)
+            .or_else(|| letter_keystroke(&AZERTY_LETTERS, c))
+            .or_else(|| table_keystroke(FRENCH_AZERTY_TABLE, c))
+    }
+}
+
+/// Errors that can occur while typing text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeTextError {
+    /// The layout has no key strokes for these characters. Nothing was typed.
+    Unmappable(Vec<char>),
+    /// The keyboard writer failed part way through the text
+    Input(InputFlowError),
+}
+
+impl std::fmt::Display for TypeTextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TypeTextError::Unmappable(chars) => {
+                write!(
+                    f,
+                    "characters {chars:?} cannot be typed on this keyboard layout"
+                )
+            }
+            TypeTextError::Input(e) => write!(f, "typing failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TypeTextError {}
+
+impl From<InputFlowError> for TypeTextError {
+    fn from(e: InputFlowError) -> Self {
+        TypeTextError::Input(e)
+    }
+}
+
+/// Translates `text` into key strokes, listing every character the layout cannot produce
+pub fn keystrokes(
+    text: &str,
+    layout: &(impl KeyboardLayout + ?Sized),
+) -> Result<Vec<KeyStroke>, TypeTextError> {
+    let mut strokes = Vec::with_capacity(text.len());
+    let mut unmappable = Vec::new();
+
+    for c in text.chars() {
+        // '\0' marks empty slots in the layout tables, it is never typeable
+        match layout.keystroke(c).filter(|_| c != '\0') {
+            Some(stroke) => strokes.push(stroke),
+            None if !unmappable.contains(&c) => unmappable.push(c),
+            None => {}
+        }
+    }
+
+    if unmappable.is_empty() {
+        Ok(strokes)
+    } else {
+        Err(TypeTextError::Unmappable(unmappable))
+    }
+}
+
+/// Types strings over any [`KeyboardWriter`]
+pub trait TypeText: KeyboardWriter {
+    /// Types `text` as it would be typed on a keyboard with the given `layout`.
+    ///
+    /// The whole text is checked before anything is sent, so text containing characters the layout
+    /// cannot produce is rejected without typing a partial string.
+    fn type_text(
+        &mut self,
+        text: &str,
+        layout: &(impl KeyboardLayout + ?Sized),
+    ) -> Result<(), TypeTextError> {
+        for stroke in keystrokes(text, layout)? {
+            let modifier = stroke.modifier.key();
+
+            if let Some(modifier) = modifier {
+                self.send_key_down(modifier)?;
+            }
+
+            let result = self
+                .send_key_down(stroke.key)
+                .and_then(|_| self.send_key_up(stroke.key));
+
+            // always let go of the modifier, even if the key itself failed
+            if let Some(modifier) = modifier {
+                self.send_key_up(modifier)?;
+            }
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: KeyboardWriter + ?Sized> TypeText for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+
+    /// Records the key events it is sent, failing for `failing_key`
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<(KeyboardKey, bool)>,
+        failing_key: Option<KeyboardKey>,
+    }
+
+    impl KeyboardWriter for Recorder {
+        fn send_key_down(&mut self, key: KeyboardKey) -> Result<()> {
+            if Some(key) == self.failing_key {
+                return Err(InputFlowError::SendError);
+            }
+            self.events.push((key, true));
+            Ok(())
+        }
+
+        fn send_key_up(&mut self, key: KeyboardKey) -> Result<()> {
+            self.events.push((key, false));
+            Ok(())
+        }
+
+        fn press_key(&mut self, key: KeyboardKey) -> Result<()> {
+            self.send_key_down(key)?;
+            self.send_key_up(key)
+        }
+
+        fn clear_keys(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn azerty_and_qwertz_letters() {
+        assert_eq!(
+            FrenchAzertyLayout.keystroke('a'),
+            Some(KeyStroke::new(Q, Modifier::None))
+        );
+        assert_eq!(
+            FrenchAzertyLayout.keystroke('M'),
+            Some(KeyStroke::new(OEM_1, Shift))
+        );
+        assert_eq!(
+            GermanLayout.keystroke('z'),
+            Some(KeyStroke::new(Y, Modifier::None))
+        );
+        assert_eq!(GermanLayout.keystroke('Y'), Some(KeyStroke::new(Z, Shift)));
+        assert_eq!(
+            UsLayout.keystroke('z'),
+            Some(KeyStroke::new(Z, Modifier::None))
+        );
+    }
+
+    #[test]
+    fn altgr_characters() {
+        assert_eq!(GermanLayout.keystroke('@'), Some(KeyStroke::new(Q, AltGr)));
+        assert_eq!(
+            FrenchAzertyLayout.keystroke('@'),
+            Some(KeyStroke::new(KEY_0, AltGr))
+        );
+        assert_eq!(UsLayout.keystroke('@'), Some(KeyStroke::new(KEY_2, Shift)));
+        assert_eq!(UkLayout.keystroke('€'), Some(KeyStroke::new(KEY_4, AltGr)));
+    }
+
+    #[test]
+    fn unmappable_characters_are_reported() {
+        assert_eq!(
+            keystrokes("ä€ä\0", &UsLayout),
+            Err(TypeTextError::Unmappable(vec!['ä', '€', '\0']))
+        );
+        // dead keys are not mapped
+        assert_eq!(
+            keystrokes("^", &GermanLayout),
+            Err(TypeTextError::Unmappable(vec!['^']))
+        );
+
+        let mut recorder = Recorder::default();
+        assert!(recorder.type_text("ok ä", &UsLayout).is_err());
+        assert!(recorder.events.is_empty());
+    }
+
+    #[test]
+    fn typing_holds_modifiers_around_keys() {
+        let mut recorder = Recorder::default();
+        recorder.type_text("a@", &GermanLayout).unwrap();
+        assert_eq!(
+            recorder.events,
+            [
+                (A, true),
+                (A, false),
+                (RMENU, true),
+                (Q, true),
+                (Q, false),
+                (RMENU, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn modifiers_are_released_when_a_key_fails() {
+        let mut recorder = Recorder {
+            failing_key: Some(KEY_2),
+            ..Default::default()
+        };
+        assert_eq!(
+            recorder.type_text("A@b", &UsLayout),
+            Err(TypeTextError::Input(InputFlowError::SendError))
+        );
+        assert_eq!(
+            recorder.events,
+            [
+                (LSHIFT, true),
+                (A, true),
+                (A, false),
+                (LSHIFT, false),
+                (LSHIFT, true),
+                (LSHIFT, false),
+            ]
+        );
+    }
+}
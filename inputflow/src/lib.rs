@@ -1,17 +1,27 @@
 pub mod api_traits;
+pub mod args;
 pub mod error;
+pub mod events;
+pub mod handle;
 pub mod headers;
+pub mod inventory;
 pub mod key_types;
+pub mod layout;
+pub mod logger;
+mod macros;
+pub mod motion;
+pub mod plugin_path;
 
+use ::std::ffi::CString;
 use abi_stable::type_layout::TypeLayout;
 use abi_stable::StableAbi;
 use api_traits::{ControllerFeatures, Loadable};
 use cglue::prelude::v1::{trait_group::compare_layouts, *};
 use core::mem::MaybeUninit;
-use ::std::ffi::CString;
 use error::{InputFlowError, Result};
+pub use handle::{load, LoadError, PluginHandle};
 use headers::PluginHeader;
-use libloading::{library_filename, Library, Symbol};
+use libloading::{Library, Symbol};
 
 #[cglue_trait]
 pub trait PluginInner<'a> {
@@ -39,6 +49,9 @@ impl<T: for<'a> PluginInner<'a>> Plugin for T {}
 
 /// Load a plugin from a given library.
 ///
+/// `name` is either a path to the library file or a bare plugin name that is searched for
+/// as described in [`plugin_path`].
+///
 /// # Safety
 ///
 /// Input library must implement a correct `create_plugin` and `get_root_layout()` functions.
@@ -50,35 +63,75 @@ impl<T: for<'a> PluginInner<'a>> Plugin for T {}
 /// Where `T` is any type, since it's opaque. Meanwhile, `get_root_layout` should simply
 /// [call the one in this crate](self::get_root_layout). It is used to verify
 /// version mismatches.
+///
+/// On failure a description of the error is available from [`error::take_last_error`].
 #[no_mangle]
 pub unsafe extern "C" fn load_plugin(
     name: ReprCStr<'_>,
     args: ReprCStr<'_>,
     ok_out: &mut MaybeUninit<PluginInnerArcBox<'static>>,
 ) -> i32 {
-    load_plugin_impl(name.as_ref(), args.as_ref()).into_int_out_result(ok_out)
+    error::catch_panic(|| load_plugin_impl(name.as_ref(), args.as_ref()))
+        .into_int_out_result(ok_out)
 }
 
 unsafe fn load_plugin_impl(name: &str, args: &str) -> Result<PluginInnerArcBox<'static>> {
-    let mut current_exe = std::env::current_exe().map_err(|_| InputFlowError::Path)?;
-    current_exe.set_file_name(library_filename(name));
-    let lib = Library::new(current_exe).map_err(|e| {
-        println!("{}", e);
-        InputFlowError::Loading
-    })?;
+    let plugin = plugin_path::find_plugin(name)
+        .map_err(LoadError::NotFound)
+        .and_then(|path| load_library(&path))
+        .and_then(|(lib, header)| create_plugin(CArc::from(lib), header, args));
+    plugin.map_err(|e| {
+        let message = e.to_string();
+        InputFlowError::from(e).with_message(message)
+    })
+}
 
-    let header: Symbol<&'static PluginHeader> = lib.get(b"IF_PLUGIN_HEAD\0").map_err(|e| {
-        println!("{}", e);
-        InputFlowError::Symbol
+/// Opens a plugin library and returns its header once the layout is verified to match ours.
+///
+/// # Safety
+///
+/// Loading a library runs its initialization code, the library at `path` must be trusted.
+unsafe fn load_library(
+    path: &std::path::Path,
+) -> std::result::Result<(Library, &'static PluginHeader), LoadError> {
+    let lib = Library::new(path).map_err(|source| LoadError::Library {
+        path: path.to_path_buf(),
+        source,
     })?;
+
+    let header: Symbol<&'static PluginHeader> =
+        lib.get(b"IF_PLUGIN_HEAD\0")
+            .map_err(|source| LoadError::NotAPlugin {
+                path: path.to_path_buf(),
+                source,
+            })?;
     let header = header.into_raw();
 
     if !compare_layouts(Some(ROOT_LAYOUT), Some(header.layout)).is_valid_strict() {
-        return Err(InputFlowError::Abi);
+        return Err(LoadError::Abi {
+            path: path.to_path_buf(),
+        });
     }
 
-    let arc = CArc::from(lib);
-    (header.create)(&arc.into_opaque(), CString::new(args).unwrap().into_raw())
+    Ok((lib, *header))
+}
+
+/// Instantiates a plugin from an already verified library.
+fn create_plugin(
+    lib: CArc<Library>,
+    header: &PluginHeader,
+    args: &str,
+) -> std::result::Result<PluginInnerArcBox<'static>, LoadError> {
+    let args = CString::new(args)
+        .map_err(|_| LoadError::Arguments("arguments contain a nul byte".into()))?;
+    let logger = logger::PluginLogger::new();
+    // the plugin only borrows the string for the duration of the call
+    (header.create)(&lib.into_opaque(), args.as_ptr(), Some(&logger)).map_err(|error| {
+        LoadError::Create {
+            error,
+            message: (header.last_error)().into_option().map(Into::into),
+        }
+    })
 }
 
 /// Layout for the root vtable.
@@ -93,6 +146,9 @@ pub mod cglue {
     pub use ::cglue::prelude::v1::*;
 }
 
+#[doc(hidden)]
+pub use log as __log;
+
 #[doc(hidden)]
 pub mod abi_stable {
     pub use abi_stable::*;
@@ -104,11 +160,19 @@ pub mod prelude {
     pub mod v1 {
         pub use crate::abi_stable;
         pub use crate::api_traits::*;
+        pub use crate::args::*;
         pub use crate::cglue::*;
         pub use crate::error::*;
+        pub use crate::events::*;
+        pub use crate::handle::*;
         pub use crate::headers::*;
+        pub use crate::inventory::*;
         pub use crate::iter::*;
         pub use crate::key_types::*;
+        pub use crate::layout::*;
+        pub use crate::logger::*;
+        pub use crate::motion::*;
+        pub use crate::plugin_path::*;
         pub use crate::*;
     }
     pub use v1::*;
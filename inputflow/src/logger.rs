@@ -0,0 +1,157 @@
+//! Forwarding of plugin log records to the host logger.
+//!
+//! Each plugin library links its own copy of the `log` crate, so a logger installed by the host
+//! is not visible from inside a plugin. The host passes a [`PluginLogger`] into `create`, and the
+//! plugin installs it with [`PluginLogger::init`]. Every record the plugin logs is then handed back
+//! to whatever logger the host set up.
+
+use std::sync::OnceLock;
+
+use abi_stable::{std_types::RStr, StableAbi};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// A log record passed from a plugin to the host
+#[repr(C)]
+#[derive(StableAbi)]
+pub struct LogRecord<'a> {
+    /// [`Level`] as integer, 1 (error) to 5 (trace)
+    pub level: u8,
+    pub target: RStr<'a>,
+    pub message: RStr<'a>,
+    /// Empty if unknown
+    pub module_path: RStr<'a>,
+    /// Empty if unknown
+    pub file: RStr<'a>,
+    /// 0 if unknown
+    pub line: u32,
+}
+
+/// Logger handed to plugins by the host.
+///
+/// The functions are implemented in the host and forward to its global [`log::logger`].
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginLogger {
+    /// [`LevelFilter`] as integer, 0 (off) to 5 (trace)
+    pub max_level: u8,
+    pub enabled: extern "C" fn(level: u8, target: RStr) -> bool,
+    pub log: extern "C" fn(record: &LogRecord),
+    pub flush: extern "C" fn(),
+}
+
+fn level_from_u8(level: u8) -> Level {
+    match level {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+fn level_filter_from_u8(level: u8) -> LevelFilter {
+    match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+extern "C" fn host_enabled(level: u8, target: RStr) -> bool {
+    log::logger().enabled(
+        &Metadata::builder()
+            .level(level_from_u8(level))
+            .target(target.as_str())
+            .build(),
+    )
+}
+
+extern "C" fn host_log(record: &LogRecord) {
+    let module_path = Some(record.module_path.as_str()).filter(|m| !m.is_empty());
+    let file = Some(record.file.as_str()).filter(|f| !f.is_empty());
+    log::logger().log(
+        &Record::builder()
+            .args(format_args!("{}", record.message.as_str()))
+            .level(level_from_u8(record.level))
+            .target(record.target.as_str())
+            .module_path(module_path)
+            .file(file)
+            .line(Some(record.line).filter(|l| *l != 0))
+            .build(),
+    );
+}
+
+extern "C" fn host_flush() {
+    log::logger().flush()
+}
+
+impl PluginLogger {
+    /// Creates a logger forwarding to the host's global logger.
+    ///
+    /// The maximum level is captured now, set up logging before loading plugins.
+    pub fn new() -> Self {
+        Self {
+            max_level: log::max_level() as usize as u8,
+            enabled: host_enabled,
+            log: host_log,
+            flush: host_flush,
+        }
+    }
+
+    /// Installs this logger as the `log` backend of the calling plugin.
+    ///
+    /// Only the first call in a plugin library has an effect, later loads of the same library
+    /// keep forwarding to the logger they were first given.
+    pub fn init(&self) {
+        if FORWARD_TO.set(*self).is_ok() && log::set_logger(&PluginLogForwarder).is_ok() {
+            log::set_max_level(level_filter_from_u8(self.max_level));
+        }
+    }
+}
+
+impl Default for PluginLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static FORWARD_TO: OnceLock<PluginLogger> = OnceLock::new();
+
+/// The `log` backend of a plugin, sends records to the host through [`FORWARD_TO`]
+struct PluginLogForwarder;
+
+impl Log for PluginLogForwarder {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FORWARD_TO.get().is_some_and(|logger| {
+            (logger.enabled)(metadata.level() as usize as u8, metadata.target().into())
+        })
+    }
+
+    fn log(&self, record: &Record) {
+        let Some(logger) = FORWARD_TO.get() else {
+            return;
+        };
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        (logger.log)(&LogRecord {
+            level: record.level() as usize as u8,
+            target: record.target().into(),
+            message: message.as_str().into(),
+            module_path: record.module_path().unwrap_or_default().into(),
+            file: record.file().unwrap_or_default().into(),
+            line: record.line().unwrap_or_default(),
+        });
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = FORWARD_TO.get() {
+            (logger.flush)()
+        }
+    }
+}
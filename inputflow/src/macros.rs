@@ -0,0 +1,239 @@
+//! Macros generating the boilerplate every plugin library needs.
+
+/// Defines the entry point of a plugin library.
+///
+/// Generates the `PluginInner` root, the cglue group implementation, the `create` function
+/// (logger setup, argument parsing, panic containment) and the exported `IF_PLUGIN_HEAD`.
+/// The header features are derived from `traits`, so they always match the vtables.
+///
+/// ```ignore
+/// inputflow::define_plugin! {
+///     controller: InputFlowQmp,
+///     args: args::Args,
+///     arg_help: args::ARG_HELP,
+///     create: InputFlowQmp::new,
+///     traits: { MouseWriter, MouseAbsoluteWriter, EventWriter },
+///     name: "qmp",
+///     description: "Sends mouse input to a qemu virtual machine over qmp",
+/// }
+/// ```
+///
+/// `create` is called with the parsed `args` and returns `Result<controller>`.
+/// Plugins without arguments use [`NoArgs`](crate::args::NoArgs) and an empty `arg_help`.
+/// Plugins that can find their devices add `devices: <fn() -> Result<Vec<DeviceDescriptor>>>`,
+/// which is exported as [`PluginHeader::list_devices`](crate::headers::PluginHeader::list_devices).
+#[macro_export]
+macro_rules! define_plugin {
+    (
+        controller: $controller:ident,
+        args: $args:ty,
+        arg_help: $arg_help:expr,
+        create: $create:expr,
+        traits: { $($trait:ident),* $(,)? },
+        name: $name:literal,
+        description: $description:literal
+        $(, devices: $devices:expr)? $(,)?
+    ) => {
+        pub use __inputflow_plugin::IF_PLUGIN_HEAD;
+
+        mod __inputflow_plugin {
+            // the plugin is expected to import `inputflow::prelude::*`, which the cglue macros rely on
+            use super::*;
+
+            const ARG_HELP: &[ArgDescriptor] = $arg_help;
+
+            struct PluginRoot {
+                controller: $controller,
+            }
+
+            impl<'a> PluginInner<'a> for PluginRoot {
+                type BorrowedType = Fwd<&'a mut $controller>;
+
+                type OwnedType = $controller;
+                type OwnedTypeMut = $controller;
+
+                fn borrow_features(&'a mut self) -> Self::BorrowedType {
+                    self.controller.forward_mut()
+                }
+
+                fn into_features(self) -> Self::OwnedType {
+                    self.controller
+                }
+
+                fn mut_features(&'a mut self) -> &'a mut Self::OwnedTypeMut {
+                    &mut self.controller
+                }
+            }
+
+            cglue_impl_group!($controller, ControllerFeatures, { $($trait),* }, { $($trait),* });
+
+            /// Exposed interface that is called by the user of the plugin to instantiate it
+            #[allow(improper_ctypes_definitions)] // the linter is being stupid and not noticing the repr(u8)
+            extern "C" fn create_plugin(
+                lib: &CArc<cglue::trait_group::c_void>,
+                args: *const std::ffi::c_char,
+                logger: Option<&PluginLogger>,
+            ) -> Result<PluginInnerArcBox<'static>> {
+                catch_panic(|| {
+                    if let Some(logger) = logger {
+                        logger.init();
+                    }
+
+                    // safety: the host keeps the string alive for the duration of `create`
+                    let args = unsafe { ConnectorArgs::from_raw(args)? };
+                    if !args.is_empty() {
+                        $crate::__log::info!("{} received args: {args:?}", $name);
+                    }
+                    let args: $args = args.parse_into(ARG_HELP).inspect_err(|e| {
+                        $crate::__log::error!("Invalid parameters were passed to {}: {e}.", $name)
+                    })?;
+
+                    let root = PluginRoot {
+                        controller: ($create)(args)?,
+                    };
+                    Ok(trait_obj!((root, lib.clone()) as PluginInner))
+                })
+            }
+
+            $(
+                /// Lists the devices the plugin can connect to, exported in the header
+                #[allow(improper_ctypes_definitions)] // see create_plugin
+                extern "C" fn list_devices() -> Result<abi_stable::std_types::RVec<DeviceDescriptor>> {
+                    catch_panic(|| ($devices)().map(|devices| devices.into_iter().collect()))
+                }
+            )?
+
+            /// Static plugin header values defining the plugin's capabilities
+            #[no_mangle]
+            pub static IF_PLUGIN_HEAD: PluginHeader = PluginHeader {
+                features: FeatureSupport::from_bits_retain(0 $(| $crate::__trait_features!($trait))*),
+                layout: ROOT_LAYOUT,
+                create: create_plugin,
+                name: abi_stable::std_types::RStr::from_str($name),
+                version: abi_stable::std_types::RStr::from_str(env!("CARGO_PKG_VERSION")),
+                description: abi_stable::std_types::RStr::from_str($description),
+                args: abi_stable::std_types::RSlice::from_slice(ARG_HELP),
+                last_error: plugin_last_error,
+                list_devices: $crate::__if_given!(($($devices)?) list_devices),
+            };
+        }
+    };
+}
+
+/// Defines a plugin's argument struct from a single table of fields.
+///
+/// Every field is listed once with its doc comment, type and default value, from which the macro
+/// generates the struct, its [`Default`] implementation and the `ARG_HELP` descriptors passed to
+/// [`define_plugin!`]. The doc comment of a field is also its description in the plugin header.
+/// The struct has to derive `Deserialize`, left out arguments are filled in from [`Default`].
+///
+/// ```ignore
+/// inputflow::define_args! {
+///     #[derive(Debug, serde::Serialize, serde::Deserialize)]
+///     pub(crate) struct Args {
+///         /// The ip address to connect to for qmp
+///         address: String = "127.0.0.1",
+///         /// The qmp port as exposed by qemu
+///         port: u32 = 6448,
+///     }
+/// }
+/// ```
+///
+/// Arguments that may be unset are typed `Option<T>` with the default `None`.
+/// A field whose default differs between platforms is listed once per platform,
+/// with a `#[cfg(...)]` after its doc comment.
+#[macro_export]
+macro_rules! define_args {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $(#[cfg($cfg:meta)])?
+                $field:ident: $ty:ty = $default:expr
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[serde(default)]
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])?
+                pub $field: $ty,
+            )*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    $(
+                        $(#[cfg($cfg)])?
+                        $field: $crate::args::default_value(stringify!($field), stringify!($default)),
+                    )*
+                }
+            }
+        }
+
+        /// Argument help exported in the plugin header, generated from the fields of the argument struct
+        $vis const ARG_HELP: &[$crate::headers::ArgDescriptor] = &[
+            $(
+                $(#[cfg($cfg)])?
+                $crate::headers::ArgDescriptor::new(
+                    stringify!($field),
+                    stringify!($ty),
+                    stringify!($default),
+                    concat!($($doc),*).trim_ascii(),
+                ),
+            )*
+        ];
+    };
+}
+
+/// Expands to `Some($then)` if the parenthesized tokens are not empty, `None` otherwise
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_given {
+    (() $then:expr) => {
+        None
+    };
+    (($($given:tt)+) $then:expr) => {
+        Some($then)
+    };
+}
+
+/// Maps an optional trait of [`ControllerFeatures`](crate::api_traits::ControllerFeatures)
+/// to the [`FeatureSupport`](crate::headers::FeatureSupport) bits it provides
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trait_features {
+    (KeyboardWriter) => {
+        $crate::headers::FeatureSupport::WRITE_KEYBOARD.bits()
+    };
+    (KeyboardReader) => {
+        $crate::headers::FeatureSupport::READ_KEYBOARD.bits()
+    };
+    (MouseWriter) => {
+        $crate::headers::FeatureSupport::WRITE_MOUSE.bits()
+    };
+    (MouseAbsoluteWriter) => {
+        $crate::headers::FeatureSupport::WRITE_MOUSE.bits()
+    };
+    (SmoothMouseWriter) => {
+        $crate::headers::FeatureSupport::WRITE_MOUSE.bits()
+    };
+    (MouseReader) => {
+        $crate::headers::FeatureSupport::READ_MOUSE.bits()
+    };
+    (InputInterceptor) => {
+        $crate::headers::FeatureSupport::INTERCEPT_MOUSE.bits()
+            | $crate::headers::FeatureSupport::INTERCEPT_KEYBOARD.bits()
+    };
+    // batching and connection monitoring have no feature bits of their own
+    (EventWriter) => {
+        0
+    };
+    (ConnectionMonitor) => {
+        0
+    };
+}
@@ -0,0 +1,258 @@
+//! Mouse moves spread out over time.
+//!
+//! Plugins implementing [`SmoothMouseWriter`] let the device do the smoothing, for all others the
+//! move is interpolated on the host and sent as many small relative moves.
+
+use std::time::{Duration, Instant};
+
+use cglue::prelude::v1::{trait_group::c_void, *};
+
+use crate::{
+    api_traits::*,
+    error::{InputFlowError, Result},
+};
+
+/// Time between two relative moves of a move interpolated on the host
+pub const STEP_INTERVAL: Duration = Duration::from_millis(4);
+
+impl<'a> ControllerFeatures<'a, CBox<'a, c_void>, CArc<c_void>> {
+    /// Moves the mouse by `x`, `y` in a straight line over `duration`.
+    ///
+    /// Uses the device side smoothing of the plugin if it implements [`SmoothMouseWriter`],
+    /// otherwise the move is interpolated through [`MouseWriter`] and this call blocks for `duration`.
+    pub fn move_relative_over(&mut self, x: i32, y: i32, duration: Duration) -> Result<()> {
+        if let Some(writer) = as_mut!(self impl SmoothMouseWriter) {
+            return writer.move_relative_over(x, y, duration_ms(duration));
+        }
+
+        let mouse = as_mut!(self impl MouseWriter).ok_or(InputFlowError::Unsupported)?;
+        interpolate_move(mouse, x, y, duration, None)
+    }
+
+    /// Moves the mouse by `x`, `y` along a cubic bezier curve bent by `controls` over `duration`.
+    ///
+    /// Falls back to host side interpolation like [`Self::move_relative_over`].
+    pub fn move_bezier_over(
+        &mut self,
+        x: i32,
+        y: i32,
+        duration: Duration,
+        controls: BezierControls,
+    ) -> Result<()> {
+        if let Some(writer) = as_mut!(self impl SmoothMouseWriter) {
+            return writer.move_bezier_over(x, y, duration_ms(duration), controls);
+        }
+
+        let mouse = as_mut!(self impl MouseWriter).ok_or(InputFlowError::Unsupported)?;
+        interpolate_move(mouse, x, y, duration, Some(controls))
+    }
+}
+
+fn duration_ms(duration: Duration) -> u32 {
+    duration.as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+/// Moves `mouse` by `x`, `y` over `duration` with one relative move every [`STEP_INTERVAL`].
+///
+/// The path is a straight line, or a cubic bezier curve if `controls` are given.
+/// Blocks until the move is done. The steps always add up to exactly `x`, `y`.
+pub fn interpolate_move<M: MouseWriter + ?Sized>(
+    mouse: &mut M,
+    x: i32,
+    y: i32,
+    duration: Duration,
+    controls: Option<BezierControls>,
+) -> Result<()> {
+    let end = (x as f64, y as f64);
+    // evenly spaced control points on the line make the curve a straight move at constant speed
+    let (c1, c2) = match controls {
+        Some(c) => ((c.x1 as f64, c.y1 as f64), (c.x2 as f64, c.y2 as f64)),
+        None => (
+            (end.0 / 3.0, end.1 / 3.0),
+            (end.0 * 2.0 / 3.0, end.1 * 2.0 / 3.0),
+        ),
+    };
+
+    let steps = (duration.as_nanos() / STEP_INTERVAL.as_nanos()).max(1) as u32;
+    let start = Instant::now();
+    let mut sent = (0, 0);
+
+    for step in 1..=steps {
+        let t = step as f64 / steps as f64;
+        let position = if step == steps {
+            (x, y)
+        } else {
+            let (px, py) = cubic_bezier(t, c1, c2, end);
+            (px.round() as i32, py.round() as i32)
+        };
+
+        if let Some(wait) = (start + duration.mul_f64(t)).checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+
+        let (dx, dy) = (position.0 - sent.0, position.1 - sent.1);
+        if dx != 0 || dy != 0 {
+            mouse.mouse_move_relative(dx, dy)?;
+            sent = position;
+        }
+    }
+
+    Ok(())
+}
+
+/// Point at `t` of the cubic bezier curve starting at the origin
+fn cubic_bezier(t: f64, c1: (f64, f64), c2: (f64, f64), end: (f64, f64)) -> (f64, f64) {
+    let u = 1.0 - t;
+    let (a, b, c) = (3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (
+        a * c1.0 + b * c2.0 + c * end.0,
+        a * c1.1 + b * c2.1 + c * end.1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::MouseButton;
+
+    /// Records the relative moves it is sent
+    #[derive(Default)]
+    struct Recorder {
+        moves: Vec<(i32, i32)>,
+    }
+
+    impl Recorder {
+        /// Position after every move, starting from the origin
+        fn positions(&self) -> Vec<(i32, i32)> {
+            self.moves
+                .iter()
+                .scan((0, 0), |position, (dx, dy)| {
+                    *position = (position.0 + dx, position.1 + dy);
+                    Some(*position)
+                })
+                .collect()
+        }
+
+        fn total(&self) -> (i32, i32) {
+            self.positions().last().copied().unwrap_or_default()
+        }
+    }
+
+    impl MouseWriter for Recorder {
+        fn send_button_down(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn send_button_up(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn click_button(&mut self, _: MouseButton) -> Result<()> {
+            Ok(())
+        }
+
+        fn clear_buttons(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn mouse_move_relative(&mut self, x: i32, y: i32) -> Result<()> {
+            self.moves.push((x, y));
+            Ok(())
+        }
+    }
+
+    fn interpolate(
+        x: i32,
+        y: i32,
+        duration: Duration,
+        controls: Option<BezierControls>,
+    ) -> Recorder {
+        let mut mouse = Recorder::default();
+        interpolate_move(&mut mouse, x, y, duration, controls).unwrap();
+        mouse
+    }
+
+    #[test]
+    fn steps_add_up_to_the_move() {
+        for (x, y, ms) in [
+            (-7, 13, 12),
+            (1000, -1, 28),
+            (5, 0, 100),
+            (-3, -3, 40),
+            (i32::MAX / 2, i32::MIN / 2, 8),
+        ] {
+            let mouse = interpolate(x, y, Duration::from_millis(ms), None);
+            assert_eq!(mouse.total(), (x, y), "{x}, {y} over {ms}ms");
+
+            // one move per interval at most, each one heading the same way as the whole move
+            assert!(mouse.moves.len() as u64 <= ms / 4);
+            for (dx, dy) in &mouse.moves {
+                assert!(dx.signum() * x.signum() >= 0 && dy.signum() * y.signum() >= 0);
+            }
+        }
+    }
+
+    #[test]
+    fn straight_moves_are_evenly_spaced() {
+        let mouse = interpolate(100, -40, Duration::from_millis(20), None);
+        assert_eq!(mouse.moves, [(20, -8); 5]);
+    }
+
+    #[test]
+    fn zero_duration_moves_at_once() {
+        let start = Instant::now();
+        let mouse = interpolate(-15, 7, Duration::ZERO, None);
+        assert_eq!(mouse.moves, [(-15, 7)]);
+        assert!(start.elapsed() < STEP_INTERVAL * 5);
+    }
+
+    #[test]
+    fn zero_distance_sends_nothing() {
+        let start = Instant::now();
+        let mouse = interpolate(0, 0, Duration::from_millis(20), None);
+        assert!(mouse.moves.is_empty());
+        // the move still takes its time
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        assert!(interpolate(0, 0, Duration::ZERO, None).moves.is_empty());
+    }
+
+    #[test]
+    fn bezier_steps_follow_the_curve() {
+        let controls = BezierControls {
+            x1: 100,
+            y1: -100,
+            x2: -50,
+            y2: 200,
+        };
+        let mouse = interpolate(10, 10, Duration::from_millis(40), Some(controls));
+
+        let mut expected: Vec<(i32, i32)> = (1..10)
+            .map(|step| {
+                let (x, y) = cubic_bezier(
+                    step as f64 / 10.0,
+                    (100.0, -100.0),
+                    (-50.0, 200.0),
+                    (10.0, 10.0),
+                );
+                (x.round() as i32, y.round() as i32)
+            })
+            .collect();
+        expected.push((10, 10));
+        expected.dedup();
+        assert_eq!(mouse.positions(), expected);
+
+        // bent away from the straight line towards the control points
+        assert!(expected.iter().any(|&(x, y)| x > 30 && y < 0));
+        assert!(expected.iter().any(|&(_, y)| y > 30));
+    }
+
+    #[test]
+    fn curve_points() {
+        let (c1, c2, end) = ((30.0, 0.0), (0.0, 60.0), (90.0, 90.0));
+        assert_eq!(cubic_bezier(0.0, c1, c2, end), (0.0, 0.0));
+        assert_eq!(cubic_bezier(1.0, c1, c2, end), end);
+        // 3/8 of each control point and 1/8 of the end at the middle
+        assert_eq!(cubic_bezier(0.5, c1, c2, end), (22.5, 33.75));
+    }
+}
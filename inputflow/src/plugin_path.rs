@@ -0,0 +1,116 @@
+//! Locating plugin libraries on disk.
+//!
+//! A plugin can be given as a path to the library file, or as a bare name (such as `inputflow_kmbox`)
+//! that is looked up in the following directories, in order:
+//!
+//! 1. every directory listed in the `INPUTFLOW_PLUGIN_PATH` environment variable
+//! 2. the directory of the running executable (and its parent when running from cargo's `deps` folder)
+//! 3. the per-user plugin directory (`~/.local/lib/inputflow` or `%APPDATA%\inputflow`)
+//! 4. the system plugin directories (`/usr/local/lib/inputflow`, `/usr/lib/inputflow` or `%ProgramFiles%\inputflow`)
+
+use std::env::consts::DLL_EXTENSION;
+use std::path::{Path, PathBuf};
+
+use libloading::library_filename;
+
+/// Environment variable holding extra plugin directories, separated like `PATH`
+pub const PLUGIN_PATH_ENV: &str = "INPUTFLOW_PLUGIN_PATH";
+
+/// Returned when a plugin library could not be found
+#[derive(Debug, Clone)]
+pub struct PluginNotFound {
+    pub name: String,
+    /// every file that was checked, in search order
+    pub tried: Vec<PathBuf>,
+}
+
+impl std::fmt::Display for PluginNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "plugin {} not found, tried:", self.name)?;
+        for path in &self.tried {
+            write!(f, "\n  {}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PluginNotFound {}
+
+/// Returns the directories bare plugin names are looked up in, in search order.
+pub fn plugin_search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if let Some(env_paths) = std::env::var_os(PLUGIN_PATH_ENV) {
+        paths.extend(std::env::split_paths(&env_paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+    {
+        // tests and examples run from target/<profile>/deps while plugins are built into target/<profile>
+        let cargo_deps_parent = exe_dir
+            .file_name()
+            .filter(|name| *name == "deps" || *name == "examples")
+            .and(exe_dir.parent())
+            .map(Path::to_path_buf);
+        paths.push(exe_dir);
+        paths.extend(cargo_deps_parent);
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(home) = std::env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".local/lib/inputflow"));
+        }
+        paths.push(PathBuf::from("/usr/local/lib/inputflow"));
+        paths.push(PathBuf::from("/usr/lib/inputflow"));
+    }
+
+    #[cfg(windows)]
+    {
+        if let Some(app_data) = std::env::var_os("APPDATA") {
+            paths.push(PathBuf::from(app_data).join("inputflow"));
+        }
+        if let Some(program_files) = std::env::var_os("ProgramFiles") {
+            paths.push(PathBuf::from(program_files).join("inputflow"));
+        }
+    }
+
+    paths
+}
+
+/// Returns true if `name` refers to a library file rather than a bare plugin name
+fn is_path(name: &str) -> bool {
+    let path = Path::new(name);
+    path.is_absolute()
+        || path.components().count() > 1
+        || path.extension().is_some_and(|ext| ext == DLL_EXTENSION)
+}
+
+/// Resolves a plugin name or path to the library file that should be loaded.
+pub fn find_plugin(name: &str) -> Result<PathBuf, PluginNotFound> {
+    let mut tried = Vec::new();
+
+    if is_path(name) {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Ok(path);
+        }
+        tried.push(path);
+    } else {
+        let file_name = library_filename(name);
+        for dir in plugin_search_paths() {
+            let path = dir.join(&file_name);
+            if path.is_file() {
+                return Ok(path);
+            }
+            tried.push(path);
+        }
+    }
+
+    Err(PluginNotFound {
+        name: name.to_string(),
+        tried,
+    })
+}